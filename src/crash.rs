@@ -0,0 +1,122 @@
+use crate::oom::grow_memory;
+use clap::ValueEnum;
+use std::fmt::{Display, Formatter};
+use std::hint::black_box;
+
+/// A genuine in-process failure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, ValueEnum)]
+pub enum CrashKind {
+    /// Panic on the main thread (unwinds, or aborts in `panic = "abort"` builds).
+    Panic,
+    /// Abort the process (SIGABRT).
+    Abort,
+    /// Dereference an invalid pointer (SIGSEGV).
+    Segfault,
+    /// Recurse until the stack overflows.
+    StackOverflow,
+    /// Execute an illegal instruction (SIGILL).
+    IllegalInstruction,
+    /// Divide an integer by zero (SIGFPE on x86-64, a panic elsewhere).
+    DivideByZero,
+    /// Allocate memory until the process is killed by the OOM killer.
+    OutOfMemory,
+}
+
+/// Parameters for crash kinds that need them.
+#[derive(Debug, Copy, Clone)]
+pub struct CrashOptions {
+    /// The memory allocation rate for [`CrashKind::OutOfMemory`], in MiB per second.
+    pub oom_rate: f64,
+    /// The optional memory ceiling for [`CrashKind::OutOfMemory`], in MiB.
+    pub oom_limit: Option<u64>,
+}
+
+impl Display for CrashKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrashKind::Panic => write!(f, "a panic"),
+            CrashKind::Abort => write!(f, "an abort"),
+            CrashKind::Segfault => write!(f, "a segmentation fault"),
+            CrashKind::StackOverflow => write!(f, "a stack overflow"),
+            CrashKind::IllegalInstruction => write!(f, "an illegal instruction"),
+            CrashKind::DivideByZero => write!(f, "a division by zero"),
+            CrashKind::OutOfMemory => write!(f, "an out-of-memory condition"),
+        }
+    }
+}
+
+/// Crashes the process with the specified kind of failure.
+///
+/// # Arguments
+///
+/// * `kind` - The kind of failure to trigger.
+/// * `options` - Parameters of the failure.
+pub fn crash(kind: CrashKind, options: &CrashOptions) -> ! {
+    println!("Crashing with {kind}");
+    match kind {
+        CrashKind::Panic => panic!("crashie induced a panic"),
+        CrashKind::Abort => std::process::abort(),
+        CrashKind::Segfault => segfault(),
+        CrashKind::StackOverflow => {
+            stack_overflow(0);
+        }
+        CrashKind::IllegalInstruction => illegal_instruction(),
+        CrashKind::DivideByZero => divide_by_zero(),
+        CrashKind::OutOfMemory => grow_memory(options.oom_rate, options.oom_limit),
+    }
+
+    // Should any of the above return unexpectedly, at least fail.
+    eprintln!("Failed to crash with {kind}, aborting instead");
+    std::process::abort()
+}
+
+/// Writes to an address on the (unmapped) zero page.
+fn segfault() {
+    let address = black_box(8_usize) as *mut u8;
+
+    // SAFETY: This is deliberately unsound; the write is meant to fault.
+    unsafe { address.write_volatile(0) }
+}
+
+/// Recurses with large stack frames until the stack guard page is hit.
+#[allow(unconditional_recursion)]
+fn stack_overflow(depth: u64) -> u64 {
+    let frame = black_box([depth; 1024]);
+    stack_overflow(black_box(frame[0] + 1)) + frame[1023]
+}
+
+/// Executes an undefined instruction.
+fn illegal_instruction() {
+    // SAFETY: This is deliberately unsound; the instruction is meant to trap.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe {
+        std::arch::asm!("ud2")
+    }
+
+    // SAFETY: This is deliberately unsound; the instruction is meant to trap.
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+    unsafe {
+        std::arch::asm!("udf #0")
+    }
+}
+
+/// Divides an integer by zero.
+fn divide_by_zero() {
+    // SAFETY: This is deliberately unsound; the division is meant to trap.
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::asm!(
+            "div {divisor}",
+            divisor = in(reg) black_box(0_u64),
+            inout("rax") 1_u64 => _,
+            inout("rdx") 0_u64 => _,
+        )
+    }
+
+    // Integer division doesn't trap on other architectures; Rust panics instead.
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let result = black_box(1_u64) / black_box(0_u64);
+        black_box(result);
+    }
+}
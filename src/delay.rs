@@ -0,0 +1,301 @@
+use clap::ValueEnum;
+use rand::Rng;
+use rand_distr::{Distribution, Exp, LogNormal, Normal, Uniform, Weibull};
+use std::str::FromStr;
+#[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+use std::time::Duration;
+
+/// The maximum number of attempts at sampling a truncated normal distribution.
+const MAX_TRUNCATION_ATTEMPTS: usize = 1000;
+
+/// The distribution to sample delays from.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DelayDistribution {
+    /// Normal distribution around the mean, folded at zero.
+    #[default]
+    Normal,
+    /// Normal distribution around the mean, restricted to the minimum and maximum.
+    TruncatedNormal,
+    /// Uniform distribution between the minimum and maximum.
+    Uniform,
+    /// Exponential distribution with the given mean (memoryless failures).
+    Exponential,
+    /// Log-normal distribution with the given mean and standard deviation.
+    LogNormal,
+    /// Weibull distribution with the given scale (the delay) and shape.
+    Weibull,
+    /// Always the given delay.
+    Fixed,
+}
+
+/// The parameters of a delay distribution, in seconds.
+#[derive(Debug, Copy, Clone)]
+pub struct DelayParameters {
+    /// The mean, scale or fixed value of the delay.
+    pub mean: f64,
+    /// The standard deviation of the delay.
+    pub stddev: f64,
+    /// The minimum delay.
+    pub min: f64,
+    /// The optional maximum delay.
+    pub max: Option<f64>,
+    /// The shape of the Weibull distribution.
+    pub shape: f64,
+}
+
+/// A distribution of delays, in seconds.
+#[derive(Debug, Copy, Clone)]
+pub enum Delay {
+    /// Always the given delay.
+    Fixed(f64),
+    /// A normal distribution with the mean and standard deviation of the delay, folded at zero.
+    Normal(Normal<f64>),
+    /// A normal distribution with the mean and standard deviation of the delay, restricted to
+    /// the minimum and maximum by resampling.
+    TruncatedNormal {
+        normal: Normal<f64>,
+        /// The minimum delay.
+        min: f64,
+        /// The maximum delay, which may be infinite.
+        max: f64,
+    },
+    /// A uniform distribution between the minimum and maximum delay, both inclusive.
+    Uniform(Uniform<f64>),
+    /// An exponential distribution whose rate is the inverse of the mean delay.
+    Exponential(Exp<f64>),
+    /// A log-normal distribution, parameterized such that the delay itself has the configured
+    /// mean and standard deviation. These are not the parameters of the underlying normal
+    /// distribution.
+    LogNormal(LogNormal<f64>),
+    /// A Weibull distribution with the scale (the delay) and shape.
+    Weibull(Weibull<f64>),
+}
+
+impl Delay {
+    /// Creates a delay distribution from its parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `distribution` - The kind of distribution.
+    /// * `params` - The parameters of the distribution.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a description of the problem if the parameters are invalid
+    /// for the selected distribution.
+    pub fn new(distribution: DelayDistribution, params: &DelayParameters) -> Result<Self, String> {
+        let DelayParameters {
+            mean,
+            stddev,
+            min,
+            max,
+            shape,
+        } = *params;
+
+        if max.is_some_and(|max| max < min) {
+            return Err(String::from(
+                "The maximum delay must not be less than the minimum delay",
+            ));
+        }
+
+        match distribution {
+            DelayDistribution::Fixed => Ok(Delay::Fixed(mean)),
+            DelayDistribution::Normal => Ok(Delay::Normal(
+                Normal::new(mean, stddev).map_err(|e| e.to_string())?,
+            )),
+            DelayDistribution::TruncatedNormal => Ok(Delay::TruncatedNormal {
+                normal: Normal::new(mean, stddev).map_err(|e| e.to_string())?,
+                min,
+                max: max.unwrap_or(f64::INFINITY),
+            }),
+            DelayDistribution::Uniform => {
+                let max = max.filter(|max| max.is_finite()).ok_or_else(|| {
+                    String::from("The uniform distribution requires a finite maximum delay")
+                })?;
+                Ok(Delay::Uniform(Uniform::new_inclusive(min, max)))
+            }
+            DelayDistribution::Exponential if mean == 0.0 => Ok(Delay::Fixed(0.0)),
+            DelayDistribution::Exponential => Ok(Delay::Exponential(
+                Exp::new(1.0 / mean).map_err(|e| e.to_string())?,
+            )),
+            DelayDistribution::LogNormal => {
+                if mean <= 0.0 {
+                    return Err(String::from(
+                        "The log-normal distribution requires a positive mean delay",
+                    ));
+                }
+
+                // Derive the parameters of the underlying normal distribution from the
+                // desired mean and standard deviation.
+                let sigma_squared = (1.0 + (stddev * stddev) / (mean * mean)).ln();
+                let mu = mean.ln() - sigma_squared / 2.0;
+                Ok(Delay::LogNormal(
+                    LogNormal::new(mu, sigma_squared.sqrt()).map_err(|e| e.to_string())?,
+                ))
+            }
+            DelayDistribution::Weibull => Ok(Delay::Weibull(
+                Weibull::new(mean, shape).map_err(|e| e.to_string())?,
+            )),
+        }
+    }
+
+    /// Samples a delay as a duration, saturating for infinite delays.
+    #[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+    pub fn sample_duration<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        Duration::try_from_secs_f64(self.sample(rng)).unwrap_or(Duration::MAX)
+    }
+
+    /// Samples a delay, in seconds.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Delay::Fixed(value) => *value,
+            Delay::Normal(normal) => normal.sample(rng).abs(),
+            Delay::TruncatedNormal { normal, min, max } => {
+                for _ in 0..MAX_TRUNCATION_ATTEMPTS {
+                    let value = normal.sample(rng);
+                    if (*min..=*max).contains(&value) {
+                        return value;
+                    }
+                }
+
+                // The bounds are far off the mean; fall back to clamping.
+                normal.sample(rng).clamp(*min, *max)
+            }
+            Delay::Uniform(uniform) => uniform.sample(rng),
+            Delay::Exponential(exp) => exp.sample(rng),
+            Delay::LogNormal(log_normal) => log_normal.sample(rng),
+            Delay::Weibull(weibull) => weibull.sample(rng),
+        }
+    }
+}
+
+/// Parses a delay from a specification of the form `SECONDS` or `DISTRIBUTION:PARAMETERS`.
+///
+/// The parameters are separated by commas and depend on the distribution:
+///
+/// * `fixed:SECONDS`
+/// * `normal:MEAN,STDDEV`
+/// * `truncated-normal:MEAN,STDDEV,MIN,MAX`
+/// * `uniform:MIN,MAX`
+/// * `exponential:MEAN`
+/// * `log-normal:MEAN,STDDEV`
+/// * `weibull:SCALE,SHAPE`
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (distribution, parameters) = match input.split_once(':') {
+            Some((distribution, parameters)) => (
+                DelayDistribution::from_str(distribution.trim(), true)?,
+                parameters,
+            ),
+            None => (DelayDistribution::Fixed, input),
+        };
+
+        let values = parameters
+            .split(',')
+            .map(|value| match value.trim().parse::<f64>() {
+                Ok(value) if value >= 0.0 => Ok(value),
+                _ => Err(format!("Invalid delay parameter: {value}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut params = DelayParameters {
+            mean: 0.0,
+            stddev: 0.0,
+            min: 0.0,
+            max: None,
+            shape: 1.0,
+        };
+        match (distribution, values.as_slice()) {
+            (DelayDistribution::Fixed | DelayDistribution::Exponential, &[mean]) => {
+                params.mean = mean;
+            }
+            (DelayDistribution::Normal | DelayDistribution::LogNormal, &[mean, stddev]) => {
+                params.mean = mean;
+                params.stddev = stddev;
+            }
+            (DelayDistribution::TruncatedNormal, &[mean, stddev, min, max]) => {
+                params.mean = mean;
+                params.stddev = stddev;
+                params.min = min;
+                params.max = Some(max);
+            }
+            (DelayDistribution::Uniform, &[min, max]) => {
+                params.min = min;
+                params.max = Some(max);
+            }
+            (DelayDistribution::Weibull, &[scale, shape]) => {
+                params.mean = scale;
+                params.shape = shape;
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid number of parameters for the delay distribution: {input}"
+                ))
+            }
+        }
+
+        Delay::new(distribution, &params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn parses_fixed_delays() {
+        assert!(matches!("1.5".parse(), Ok(Delay::Fixed(value)) if value == 1.5));
+        assert!(matches!(" fixed: 2 ".parse(), Ok(Delay::Fixed(value)) if value == 2.0));
+        assert!(matches!("exponential:0".parse(), Ok(Delay::Fixed(value)) if value == 0.0));
+    }
+
+    #[test]
+    fn parses_distributions() {
+        assert!(matches!("normal:1,0.5".parse(), Ok(Delay::Normal(_))));
+        assert!(matches!(
+            "truncated-normal:1,0.5,0.5,2".parse(),
+            Ok(Delay::TruncatedNormal { min, max, .. }) if min == 0.5 && max == 2.0
+        ));
+        assert!(matches!("Uniform:0.1,2".parse(), Ok(Delay::Uniform(_))));
+        assert!(matches!("exponential:1".parse(), Ok(Delay::Exponential(_))));
+        assert!(matches!(
+            "log-normal:1,0.5".parse(),
+            Ok(Delay::LogNormal(_))
+        ));
+        assert!(matches!("weibull:1,2".parse(), Ok(Delay::Weibull(_))));
+    }
+
+    #[test]
+    fn samples_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let uniform: Delay = "uniform:0.1,0.2".parse().unwrap();
+        let truncated: Delay = "truncated-normal:1,5,0.5,2".parse().unwrap();
+        for _ in 0..100 {
+            assert!((0.1..=0.2).contains(&uniform.sample(&mut rng)));
+            assert!((0.5..=2.0).contains(&truncated.sample(&mut rng)));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_delays() {
+        for input in [
+            "",
+            "-1",
+            "nan",
+            "soon",
+            "gamma:1",
+            "fixed:1,2",
+            "normal:1",
+            "uniform:2,1",
+            "uniform:0,inf",
+            "log-normal:0,1",
+            "weibull:1,0",
+        ] {
+            assert!(input.parse::<Delay>().is_err(), "{input}");
+        }
+    }
+}
@@ -1,83 +1,381 @@
-use chrono::prelude::*;
-use std::io::{BufRead, BufReader, Write};
+mod announce;
+mod echo;
+mod faults;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http2;
+mod json;
+mod probes;
+mod request;
+mod response;
+mod routes;
+mod streaming;
+mod websocket;
+
+use crate::delay::Delay;
+use crate::lifecycle::{is_draining, park_forever, stall_if_frozen};
+use crate::listener::accept_connections;
+use base64::prelude::*;
+use rand::rngs::StdRng;
+#[cfg(feature = "tls")]
+use rustls::ServerConfig;
+use std::io::{BufReader, Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+
+pub use announce::announce_failure;
+#[cfg(feature = "tls")]
+use echo::TlsInfo;
+use echo::{echo, ConnectionInfo};
+use faults::Delivery;
+pub use faults::Faults;
+#[cfg(feature = "grpc")]
+pub use grpc::grpc_echo;
+pub use http2::Http2Failure;
+use http2::Upgrade;
+use probes::ProbeResponse;
+pub use probes::{LivenessFailAfter, LivenessFailure, Probes};
+use request::{read_request, Request, RequestError};
+use response::Response;
+pub use routes::{load_routes, Route};
+use streaming::Streaming;
+pub use websocket::{is_valid_close_code, WebSocketFailure};
+
+/// A connection that can be split into halves for reading and writing concurrently.
+pub trait Transport: Read + Write + Send {
+    /// The reading half of the connection.
+    type Reader: Read + Send;
+    /// The writing half of the connection.
+    type Writer: Write + Send;
+
+    /// Splits the connection into its reading and writing halves.
+    fn split(self) -> Result<(Self::Reader, Self::Writer), std::io::Error>;
+
+    /// Returns the underlying TCP connection.
+    fn socket(&self) -> &TcpStream;
+}
+
+impl Transport for TcpStream {
+    type Reader = TcpStream;
+    type Writer = TcpStream;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), std::io::Error> {
+        Ok((self.try_clone()?, self))
+    }
+
+    fn socket(&self) -> &TcpStream {
+        self
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Transport for crate::tls::TlsStream {
+    type Reader = crate::tls::TlsReader;
+    type Writer = crate::tls::TlsWriter;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), std::io::Error> {
+        crate::tls::split(self)
+    }
+
+    fn socket(&self) -> &TcpStream {
+        &self.sock
+    }
+}
 
-pub fn http_echo(addr: &SocketAddr, liveness_probe_path: String) -> Result<(), std::io::Error> {
+/// Configuration of the HTTP echo server.
+#[derive(Debug)]
+pub struct HttpEchoConfig {
+    /// The probe paths and their timelines.
+    pub probes: Probes,
+    /// The routes to respond with; the first matching route is used.
+    pub routes: Vec<Route>,
+    /// The path prefix on which requests are reflected back as JSON.
+    pub echo_path: String,
+    /// The delay before responding to requests other than probes.
+    pub latency: Option<Delay>,
+    /// The faults to inject into responses other than probes.
+    pub faults: Faults,
+    /// How open HTTP/2 connections are notified before the process fails.
+    pub http2_on_failure: Http2Failure,
+    /// The error code of the frames sent before the process fails.
+    pub http2_failure_error_code: u32,
+    /// The path on which WebSocket connections are accepted.
+    pub websocket_path: String,
+    /// How open WebSocket connections are notified before the process fails.
+    pub websocket_on_failure: WebSocketFailure,
+    /// The close code sent to WebSocket clients before the process fails.
+    pub websocket_close_code: u16,
+    /// The path on which Server-Sent Events are streamed.
+    pub sse_path: String,
+    /// The default interval between Server-Sent Events.
+    pub sse_interval: Duration,
+    /// The path on which a slow chunked response is streamed.
+    pub stream_path: String,
+    /// The default interval between chunks of the streamed response.
+    pub stream_interval: Duration,
+    /// The time before the scheduled failure during which gRPC health checks report
+    /// `NOT_SERVING`.
+    #[cfg(feature = "grpc")]
+    pub grpc_not_serving_before: Duration,
+    /// The random number generator for sampling delays and faults.
+    pub rng: Mutex<StdRng>,
+}
+
+impl HttpEchoConfig {
+    /// Returns whether open connections are notified before the process fails.
+    pub fn announces_failure(&self) -> bool {
+        self.http2_on_failure != Http2Failure::None
+            || self.websocket_on_failure != WebSocketFailure::None
+    }
+
+    /// Returns whether a probe or a configured route answers the request.
+    ///
+    /// These take precedence over the WebSocket and streaming endpoints.
+    fn is_routed(&self, request: &Request) -> bool {
+        let path = request.path();
+        self.probes.matches(path)
+            || self
+                .routes
+                .iter()
+                .any(|route| route.matches(&request.method, path))
+    }
+
+    /// Sleeps for a duration sampled from the delay, if any.
+    fn delay(&self, latency: Option<&Delay>) {
+        if let Some(latency) = latency {
+            let duration =
+                latency.sample_duration(&mut *self.rng.lock().expect("RNG lock poisoned"));
+            thread::sleep(duration);
+        }
+    }
+}
+
+pub fn http_echo(addr: &SocketAddr, config: Arc<HttpEchoConfig>) -> Result<(), std::io::Error> {
     let listener = TcpListener::bind(addr)?;
     println!("Listening for HTTP connections on {addr}");
+    accept_connections(listener, "HTTP", move |stream| {
+        let connection = ConnectionInfo {
+            peer_addr: stream.peer_addr().ok(),
+            local_addr: stream.local_addr().ok(),
+            tls: None,
+            request_number: 0,
+        };
+        handle_client(stream, connection, &config)
+    })
+}
 
-    thread::spawn(move || {
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    println!(
-                        "Accepting HTTP connection from {}",
-                        stream.peer_addr().expect("Unable to obtain peer address")
-                    );
-                    let liveness_probe_path = liveness_probe_path.clone();
-                    thread::spawn(move || handle_client(stream, liveness_probe_path));
-                }
-                Err(e) => {
-                    eprintln!("Error accepting HTTP connection: {e}");
-                }
+/// Listens for HTTPS connections on the given address.
+///
+/// # Arguments
+///
+/// * `addr` - The address to bind the TCP listener to.
+/// * `config` - The configuration of the HTTP echo server.
+/// * `tls_config` - The TLS server configuration.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if there is an error binding the TCP listener to the
+/// given address.
+#[cfg(feature = "tls")]
+pub fn https_echo(
+    addr: &SocketAddr,
+    config: Arc<HttpEchoConfig>,
+    tls_config: Arc<ServerConfig>,
+) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening for HTTPS connections on {addr}");
+    accept_connections(listener, "HTTPS", move |stream| {
+        let stream = match crate::tls::accept(&tls_config, stream) {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("TLS handshake failed: {e}");
+                return;
             }
+        };
+
+        let tls = &stream.conn;
+        let connection = ConnectionInfo {
+            peer_addr: stream.sock.peer_addr().ok(),
+            local_addr: stream.sock.local_addr().ok(),
+            tls: Some(TlsInfo {
+                server_name: tls.server_name().map(String::from),
+                version: tls
+                    .protocol_version()
+                    .and_then(|version| version.as_str())
+                    .map(String::from),
+                cipher_suite: tls
+                    .negotiated_cipher_suite()
+                    .and_then(|suite| suite.suite().as_str())
+                    .map(String::from),
+                alpn: tls
+                    .alpn_protocol()
+                    .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            }),
+            request_number: 0,
+        };
+
+        // Clients negotiating HTTP/2 start with the connection preface right away.
+        if tls.alpn_protocol() == Some(b"h2") {
+            http2::serve(
+                BufReader::new(stream),
+                &config,
+                &*config,
+                connection,
+                None,
+                http2::PREFACE,
+            );
+        } else {
+            handle_client(stream, connection, &config)
         }
-    });
-    Ok(())
+    })
 }
 
-fn handle_client(stream: TcpStream, liveness_probe_path: String) {
+fn handle_client<T: Transport>(stream: T, mut connection: ConnectionInfo, config: &HttpEchoConfig) {
     let mut reader = BufReader::new(stream);
 
     loop {
-        let mut request_line = String::new();
-        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
-            return;
-        }
+        let request = match read_request(&mut reader) {
+            Ok(request) => request,
+            Err(RequestError::Closed) => return,
+            Err(e) => {
+                eprintln!("Rejecting invalid HTTP request: {e:?}");
+                if let Some(status) = e.status() {
+                    if let Err(e) = Response::empty(status).write_to(reader.get_mut(), true) {
+                        eprintln!("Failed to write HTTP response: {e}")
+                    }
+                }
+                return;
+            }
+        };
 
-        // RFC2616: should ignore any empty line(s) (CRLF only) received
-        // where a Request-Line is expected.
-        if request_line == "\r\n" || request_line == "\n" {
-            continue;
+        // Switch to HTTP/2 if the client starts with the connection preface.
+        if request.is_http2_preface() {
+            http2::serve(reader, config, config, connection, None, b"SM\r\n\r\n");
+            return;
         }
 
-        // Extract the path from the request line.
-        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
-
-        // Read and ignore headers.
-        let mut header_line = String::new();
-        loop {
-            header_line.clear();
-            if reader.read_line(&mut header_line).unwrap_or(0) == 0 {
+        // Upgrades to HTTP/2 are only allowed on unencrypted connections.
+        if connection.tls.is_none() {
+            if let Some(settings) = h2c_upgrade_settings(&request) {
+                let switching = "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+                if let Err(e) = reader.get_mut().write_all(switching.as_bytes()) {
+                    eprintln!("Failed to write HTTP response: {e}");
+                    return;
+                }
+                let upgrade = Upgrade { request, settings };
+                http2::serve(
+                    reader,
+                    config,
+                    config,
+                    connection,
+                    Some(upgrade),
+                    http2::PREFACE,
+                );
                 return;
             }
-            if header_line == "\r\n" || header_line == "\n" {
-                break;
+        }
+
+        // Switch to a WebSocket connection on the WebSocket path, unless it is routed elsewhere.
+        if request.path() == config.websocket_path && !config.is_routed(&request) {
+            match websocket::handshake(&request) {
+                Ok(switching) => match reader.get_mut().write_all(switching.as_bytes()) {
+                    Ok(()) => websocket::serve(reader, config),
+                    Err(e) => eprintln!("Failed to write HTTP response: {e}"),
+                },
+                Err(response) => {
+                    if let Err(e) = response.write_to(reader.get_mut(), true) {
+                        eprintln!("Failed to write HTTP response: {e}")
+                    }
+                }
             }
+            return;
         }
 
-        // To retain mutable reference to the stream after use.
-        let mut stream = reader
-            .get_ref()
-            .try_clone()
-            .expect("Failed to obtain write stream");
+        connection.request_number += 1;
+        stall_if_frozen();
 
-        // Setting version and date from env variable and system time respectively.
-        let version = env!("CARGO_PKG_VERSION");
-        let date = Utc::now().format("%a, %d %b %Y %T GMT").to_string();
+        // Ask clients to close the connection when shutting down.
+        let close = is_draining() || request.wants_close();
 
-        // Prepare response based on the request path
-        let response = if path == liveness_probe_path {
-            format!(
-                "HTTP/1.1 200 OK\r\nServer: crashie/{version}\r\nDate: {date}\r\nContent-Length: 0\r\nCache-Control: no-cache, no-store\r\n\r\n")
-        } else {
-            format!(
-                "HTTP/1.1 204 No Content\r\nServer: crashie/{version}\r\nDate: {date}\r\nContent-Length: 0\r\nCache-Control: no-cache, no-store\r\n\r\n")
+        // Prepare response based on the request
+        let head_only = request.method == "HEAD";
+        let result = match Streaming::from_request(config, &request) {
+            Some(streaming) => {
+                let chunked = request.version == "HTTP/1.1";
+                streaming.write_to(reader.get_mut(), close, head_only, chunked)
+            }
+            None => {
+                let (response, delivery) = respond(config, &request, &connection);
+                delivery.write_to(reader.get_mut(), &response, close, head_only)
+            }
         };
+        match result {
+            Ok(false) => {}
+            Ok(true) => return,
+            Err(e) => {
+                eprintln!("Failed to write HTTP response: {e}");
+                return;
+            }
+        }
+    }
+}
 
-        if let Err(e) = stream.write_all(response.as_bytes()) {
-            eprintln!("Failed to write HTTP response: {e}")
+/// Determines the response to a request and how it is delivered.
+///
+/// Probes take precedence over the configured routes, which take precedence over
+/// the echo path. Requests matching none of them are answered with `204 No Content`.
+/// Except for probes, responses are delayed by the route's or the global latency
+/// and subject to fault injection.
+fn respond(
+    config: &HttpEchoConfig,
+    request: &Request,
+    connection: &ConnectionInfo,
+) -> (Response, Delivery) {
+    // Probes and routes match the path without the query string.
+    let path = request.path();
+    match config.probes.respond(path) {
+        Some(ProbeResponse::Status(status)) => {
+            return (Response::empty(status), Delivery::Complete)
         }
+        Some(ProbeResponse::Hang) => park_forever(),
+        None => {}
+    }
+
+    let global_latency = config.latency.as_ref();
+    let (response, latency) = match config
+        .routes
+        .iter()
+        .find(|route| route.matches(&request.method, path))
+    {
+        Some(route) => (route.response().clone(), route.latency().or(global_latency)),
+        None if is_echo_path(&config.echo_path, path) => {
+            (echo(request, connection), global_latency)
+        }
+        None => (Response::empty(204), global_latency),
+    };
+
+    config.delay(latency);
+    let mut rng = config.rng.lock().expect("RNG lock poisoned");
+    config.faults.inject(&mut *rng, response)
+}
+
+/// Returns the decoded `HTTP2-Settings` if the request asks to upgrade to HTTP/2.
+fn h2c_upgrade_settings(request: &Request) -> Option<Vec<u8>> {
+    if request.version != "HTTP/1.1" || !request.has_header_token("Upgrade", "h2c") {
+        return None;
+    }
+    let settings = request.header("HTTP2-Settings")?;
+    BASE64_URL_SAFE_NO_PAD
+        .decode(settings.trim_end_matches('='))
+        .ok()
+}
+
+/// Returns whether the path is the echo path or below it.
+fn is_echo_path(echo_path: &str, path: &str) -> bool {
+    match path.strip_prefix(echo_path.trim_end_matches('/')) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
     }
 }
@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// The maximum time to wait for open connections to announce the failure.
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the process is about to fail.
+static FAILING: AtomicBool = AtomicBool::new(false);
+
+/// The number of open connections that have not yet announced the failure.
+static UNANNOUNCED: AtomicUsize = AtomicUsize::new(0);
+
+/// Notifies open HTTP/2 and WebSocket connections that the process is about to fail.
+///
+/// Blocks until all connections have announced the failure, or a timeout has passed.
+pub fn announce_failure() {
+    FAILING.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + ANNOUNCE_TIMEOUT;
+    while UNANNOUNCED.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        sleep(Duration::from_millis(10));
+    }
+}
+
+/// An open connection that announces the failure to its peer.
+///
+/// The connection no longer delays the failure once the announcement is completed
+/// or the connection is dropped.
+#[derive(Debug)]
+pub struct Announcement {
+    pending: bool,
+}
+
+impl Announcement {
+    /// Registers an open connection.
+    pub fn register() -> Self {
+        UNANNOUNCED.fetch_add(1, Ordering::SeqCst);
+        Self { pending: true }
+    }
+
+    /// Returns whether the failure is imminent and not yet announced on this connection.
+    pub fn is_due(&self) -> bool {
+        self.pending && FAILING.load(Ordering::SeqCst)
+    }
+
+    /// Marks the failure as announced on this connection.
+    pub fn complete(&mut self) {
+        if self.pending {
+            self.pending = false;
+            UNANNOUNCED.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for Announcement {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
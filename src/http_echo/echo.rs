@@ -0,0 +1,168 @@
+use crate::http_echo::json::Json;
+use crate::http_echo::request::Request;
+use crate::http_echo::response::Response;
+use base64::prelude::*;
+use std::net::SocketAddr;
+
+/// Information about the connection a request was received on.
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// The address of the client.
+    pub peer_addr: Option<SocketAddr>,
+    /// The local address the request was received on.
+    pub local_addr: Option<SocketAddr>,
+    /// The negotiated TLS parameters, if the connection is encrypted.
+    pub tls: Option<TlsInfo>,
+    /// The number of the request on this connection, starting at one.
+    pub request_number: u64,
+}
+
+/// The negotiated parameters of a TLS connection.
+#[derive(Debug, Clone)]
+pub struct TlsInfo {
+    /// The server name requested by the client via SNI.
+    pub server_name: Option<String>,
+    /// The protocol version, e.g. `TLSv1_3`.
+    pub version: Option<String>,
+    /// The cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`.
+    pub cipher_suite: Option<String>,
+    /// The application protocol negotiated via ALPN, e.g. `h2`.
+    pub alpn: Option<String>,
+}
+
+/// Reflects the request back to the client as a JSON document.
+///
+/// # Arguments
+///
+/// * `request` - The request to reflect.
+/// * `connection` - Information about the connection.
+pub fn echo(request: &Request, connection: &ConnectionInfo) -> Response {
+    let mut headers: Vec<(String, Json)> = Vec::new();
+    for (name, value) in &request.headers {
+        match headers
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+        {
+            Some((_, Json::String(existing))) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            _ => headers.push((name.clone(), Json::string(value.as_str()))),
+        }
+    }
+
+    let body = match std::str::from_utf8(&request.body) {
+        Ok(text) => ("body", Json::string(text)),
+        Err(_) => (
+            "body_base64",
+            Json::String(BASE64_STANDARD.encode(&request.body)),
+        ),
+    };
+
+    let address =
+        |addr: Option<SocketAddr>| addr.map_or(Json::Null, |addr| Json::String(addr.to_string()));
+    let document = Json::Object(vec![
+        (
+            String::from("method"),
+            Json::string(request.method.as_str()),
+        ),
+        (String::from("path"), Json::string(request.path())),
+        (
+            String::from("query"),
+            request.query().map_or(Json::Null, Json::string),
+        ),
+        (
+            String::from("args"),
+            query_args(request.query().unwrap_or("")),
+        ),
+        (String::from("headers"), Json::Object(headers)),
+        (String::from(body.0), body.1),
+        (
+            String::from("connection"),
+            Json::Object(vec![
+                (String::from("peer_addr"), address(connection.peer_addr)),
+                (String::from("local_addr"), address(connection.local_addr)),
+                (
+                    String::from("protocol"),
+                    Json::string(request.version.as_str()),
+                ),
+                (String::from("tls"), tls_info(connection.tls.as_ref())),
+                (
+                    String::from("request_number"),
+                    Json::Number(connection.request_number as f64),
+                ),
+            ]),
+        ),
+    ]);
+
+    let mut body = document.to_pretty_string().into_bytes();
+    body.push(b'\n');
+    Response {
+        status: 200,
+        headers: vec![(
+            String::from("Content-Type"),
+            String::from("application/json"),
+        )],
+        body,
+    }
+}
+
+/// Describes the TLS parameters of the connection, or `null` for unencrypted connections.
+fn tls_info(tls: Option<&TlsInfo>) -> Json {
+    let Some(tls) = tls else {
+        return Json::Null;
+    };
+    let optional = |value: &Option<String>| value.as_deref().map_or(Json::Null, Json::string);
+    Json::Object(vec![
+        (String::from("server_name"), optional(&tls.server_name)),
+        (String::from("version"), optional(&tls.version)),
+        (String::from("cipher_suite"), optional(&tls.cipher_suite)),
+        (String::from("alpn"), optional(&tls.alpn)),
+    ])
+}
+
+/// Decodes the query string into its arguments; repeated keys are collected into arrays.
+fn query_args(query: &str) -> Json {
+    let mut args: Vec<(String, Json)> = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (key, value) = (percent_decode(key), Json::String(percent_decode(value)));
+        match args.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, Json::Array(values))) => values.push(value),
+            Some((_, existing)) => {
+                let first = std::mem::replace(existing, Json::Null);
+                *existing = Json::Array(vec![first, value]);
+            }
+            None => args.push((key, value)),
+        }
+    }
+    Json::Object(args)
+}
+
+/// Decodes percent-encoded characters and `+` as space.
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+            {
+                (Some(high), Some(low)) => {
+                    decoded.push(high << 4 | low);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the value of a hexadecimal digit.
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
@@ -0,0 +1,115 @@
+use crate::http_echo::response::Response;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::io::Write;
+
+/// The faults to inject into HTTP responses.
+///
+/// Each fault is rolled independently for every request; a dropped connection
+/// takes precedence over an error response, which may then be truncated or sent
+/// with a mismatched `Content-Length`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Faults {
+    /// The probability of responding with one of the error statuses.
+    pub error_probability: f64,
+    /// The statuses to pick error responses from.
+    pub error_statuses: Vec<u16>,
+    /// The value of the `Retry-After` header of error responses, in seconds.
+    pub retry_after: Option<u64>,
+    /// The probability of closing the connection without responding.
+    pub drop_probability: f64,
+    /// The probability of sending only part of the response before closing the connection.
+    pub truncate_probability: f64,
+    /// The probability of sending a `Content-Length` that does not match the body.
+    pub length_mismatch_probability: f64,
+}
+
+/// How a response is delivered to the client.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Delivery {
+    /// The response is sent completely.
+    Complete,
+    /// The connection is closed without responding.
+    Drop,
+    /// Only the given fraction of the response is sent before closing the connection.
+    Truncate(f64),
+    /// The response announces the given `Content-Length` instead of the actual one.
+    LengthMismatch(usize),
+}
+
+impl Faults {
+    /// Randomly replaces the response with an error and selects how it is delivered.
+    pub fn inject<R: Rng + ?Sized>(&self, rng: &mut R, response: Response) -> (Response, Delivery) {
+        if rng.gen_bool(self.drop_probability) {
+            return (response, Delivery::Drop);
+        }
+
+        let mut response = response;
+        if rng.gen_bool(self.error_probability) {
+            if let Some(status) = self.error_statuses.choose(rng) {
+                response = Response::empty(*status);
+                if let Some(seconds) = self.retry_after {
+                    response
+                        .headers
+                        .push((String::from("Retry-After"), seconds.to_string()));
+                }
+            }
+        }
+
+        let delivery = if rng.gen_bool(self.truncate_probability) {
+            Delivery::Truncate(rng.gen())
+        } else if rng.gen_bool(self.length_mismatch_probability) {
+            let length = response.body.len();
+            if length > 0 && rng.gen() {
+                Delivery::LengthMismatch(rng.gen_range(0..length))
+            } else {
+                Delivery::LengthMismatch(rng.gen_range(length + 1..=2 * length + 1))
+            }
+        } else {
+            Delivery::Complete
+        };
+        (response, delivery)
+    }
+}
+
+impl Delivery {
+    /// Writes the response to the stream and returns whether the connection must be closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to write to.
+    /// * `response` - The response to write.
+    /// * `close` - Whether to ask the client to close the connection.
+    /// * `head_only` - Whether to omit the body, e.g. when responding to `HEAD` requests.
+    pub fn write_to<W: Write>(
+        self,
+        stream: &mut W,
+        response: &Response,
+        close: bool,
+        head_only: bool,
+    ) -> Result<bool, std::io::Error> {
+        let body: &[u8] = if head_only { &[] } else { &response.body };
+        match self {
+            Delivery::Complete => {
+                stream.write_all(response.head(close).as_bytes())?;
+                stream.write_all(body)?;
+                Ok(close)
+            }
+            Delivery::Drop => Ok(true),
+            Delivery::Truncate(fraction) => {
+                let mut bytes = response.head(close).into_bytes();
+                bytes.extend_from_slice(body);
+                let length = (bytes.len() as f64 * fraction) as usize;
+                stream.write_all(&bytes[..length.min(bytes.len() - 1)])?;
+                Ok(true)
+            }
+            Delivery::LengthMismatch(length) => {
+                // Close the connection if the client would wait for more data.
+                let close = close || length > response.body.len();
+                stream.write_all(response.head_with_length(close, length).as_bytes())?;
+                stream.write_all(body)?;
+                Ok(close)
+            }
+        }
+    }
+}
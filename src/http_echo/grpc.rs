@@ -0,0 +1,374 @@
+mod descriptors;
+mod protobuf;
+
+use crate::http_echo::echo::ConnectionInfo;
+use crate::http_echo::http2::{self, Handler, ResponseStream, StreamFrame};
+use crate::http_echo::request::{Request, MAX_BODY_SIZE};
+use crate::http_echo::HttpEchoConfig;
+use crate::lifecycle::{is_draining, time_until_failure, POLL_INTERVAL};
+use crate::listener::accept_connections;
+use descriptors::FILES;
+use protobuf::{decode, string_field, Encoder, Value};
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread::sleep;
+
+// Status codes.
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAVAILABLE: u32 = 14;
+
+// Serving status of the health checking service.
+const SERVING: u64 = 1;
+const NOT_SERVING: u64 = 2;
+const SERVICE_UNKNOWN: u64 = 3;
+
+/// Listens for gRPC connections on the given address.
+///
+/// Serves the `grpc.health.v1.Health` service, server reflection and the `crashie.v1.Echo`
+/// service over HTTP/2 with prior knowledge.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if there is an error binding the TCP listener to the
+/// given address.
+pub fn grpc_echo(addr: &SocketAddr, config: Arc<HttpEchoConfig>) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening for gRPC connections on {addr}");
+    accept_connections(listener, "gRPC", move |stream| {
+        let connection = ConnectionInfo {
+            peer_addr: stream.peer_addr().ok(),
+            local_addr: stream.local_addr().ok(),
+            tls: None,
+            request_number: 0,
+        };
+        http2::serve(
+            BufReader::new(stream),
+            &config,
+            &GrpcService(&config),
+            connection,
+            None,
+            http2::PREFACE,
+        );
+    })
+}
+
+/// The gRPC services.
+struct GrpcService<'a>(&'a HttpEchoConfig);
+
+/// The status a call ends with.
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    fn new<S: Into<String>>(code: u32, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn ok() -> Self {
+        Self::new(OK, "")
+    }
+}
+
+impl Handler for GrpcService<'_> {
+    fn handle(
+        &self,
+        request: Request,
+        body: Receiver<Vec<u8>>,
+        _connection: ConnectionInfo,
+        stream: &ResponseStream,
+    ) {
+        let is_grpc = request
+            .header("Content-Type")
+            .is_some_and(|content_type| content_type.starts_with("application/grpc"));
+        if request.method != "POST" || !is_grpc {
+            let fields = vec![(String::from(":status"), String::from("415"))];
+            stream.send(StreamFrame::Headers(fields, true));
+            return;
+        }
+
+        let mut call = Call {
+            stream,
+            messages: Messages {
+                body,
+                buffer: request.body,
+            },
+            headers_sent: false,
+        };
+        let status = match request.target.as_str() {
+            "/grpc.health.v1.Health/Check" => self.check(&mut call),
+            "/grpc.health.v1.Health/Watch" => self.watch(&mut call),
+            "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo"
+            | "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo" => {
+                reflect(&mut call)
+            }
+            "/crashie.v1.Echo/Echo" | "/crashie.v1.Echo/EchoStream" => echo(&mut call),
+            method => Err(Status::new(
+                UNIMPLEMENTED,
+                format!("Unknown method {method}"),
+            )),
+        };
+        call.finish(status.unwrap_or_else(|status| status));
+    }
+}
+
+impl GrpcService<'_> {
+    /// Returns the serving status of the service, or `None` if the service is unknown.
+    ///
+    /// Services are serving until the scheduled failure is less than `grpc_not_serving_before`
+    /// away, or the server is draining.
+    fn serving_status(&self, service: &str) -> Option<u64> {
+        let known = service.is_empty() || FILES.iter().any(|file| file.services.contains(&service));
+        if !known {
+            return None;
+        }
+        let failing =
+            matches!(time_until_failure(), Some(left) if left <= self.0.grpc_not_serving_before);
+        if failing || is_draining() {
+            Some(NOT_SERVING)
+        } else {
+            Some(SERVING)
+        }
+    }
+
+    /// Answers `grpc.health.v1.Health/Check` with the current serving status.
+    fn check(&self, call: &mut Call) -> Result<Status, Status> {
+        let service = health_check_service(&call.unary_request()?)?;
+        match self.serving_status(&service) {
+            Some(status) => {
+                call.send(&Encoder::new().varint(1, status).finish());
+                Ok(Status::ok())
+            }
+            None => Err(Status::new(NOT_FOUND, format!("Unknown service {service}"))),
+        }
+    }
+
+    /// Answers `grpc.health.v1.Health/Watch` with the serving status whenever it changes.
+    ///
+    /// While draining, the call ends after reporting that the service is no longer serving.
+    fn watch(&self, call: &mut Call) -> Result<Status, Status> {
+        let service = health_check_service(&call.unary_request()?)?;
+        let mut reported = None;
+        loop {
+            let status = self.serving_status(&service).unwrap_or(SERVICE_UNKNOWN);
+            if reported != Some(status) {
+                if !call.send(&Encoder::new().varint(1, status).finish()) {
+                    return Ok(Status::ok());
+                }
+                reported = Some(status);
+            }
+            if is_draining() {
+                return Err(Status::new(UNAVAILABLE, "Server is shutting down"));
+            }
+            if !call.stream.is_open() {
+                return Ok(Status::ok());
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Returns the service named in a `HealthCheckRequest`.
+fn health_check_service(request: &[u8]) -> Result<String, Status> {
+    let fields = decode(request).ok_or_else(invalid_message)?;
+    Ok(string_field(&fields, 1).unwrap_or_default().to_string())
+}
+
+/// Returns each request message back unchanged, regardless of its type.
+fn echo(call: &mut Call) -> Result<Status, Status> {
+    while let Some(message) = call.messages.next()? {
+        if !call.send(&message) {
+            break;
+        }
+    }
+    Ok(Status::ok())
+}
+
+/// Answers server reflection requests, which arrive on a bidirectional stream.
+fn reflect(call: &mut Call) -> Result<Status, Status> {
+    while let Some(request) = call.messages.next()? {
+        let fields = decode(&request).ok_or_else(invalid_message)?;
+        let mut response = Encoder::new()
+            .string(1, string_field(&fields, 1).unwrap_or_default())
+            .bytes(2, &request);
+
+        let descriptors = |files: Vec<&descriptors::ProtoFile>| {
+            let descriptors = files.iter().fold(Encoder::new(), |response, file| {
+                response.bytes(1, &(file.descriptor)())
+            });
+            (4, descriptors)
+        };
+        let not_found = |message: String| {
+            let error = Encoder::new()
+                .varint(1, NOT_FOUND as u64)
+                .string(2, &message);
+            (7, error)
+        };
+
+        // The request is a oneof, of which the last field present is used.
+        let (field, message) = match fields
+            .iter()
+            .rev()
+            .find(|(field, _)| (3..=7).contains(field))
+        {
+            Some((3, Value::Bytes(name))) => {
+                let name = String::from_utf8_lossy(name);
+                let files: Vec<_> = FILES.iter().filter(|file| file.name == name).collect();
+                match files.is_empty() {
+                    true => not_found(format!("Unknown file {name}")),
+                    false => descriptors(files),
+                }
+            }
+            Some((4, Value::Bytes(symbol))) => {
+                let symbol = String::from_utf8_lossy(symbol);
+                let files: Vec<_> = FILES.iter().filter(|file| file.defines(&symbol)).collect();
+                match files.is_empty() {
+                    true => not_found(format!("Unknown symbol {symbol}")),
+                    false => descriptors(files),
+                }
+            }
+            Some((6, Value::Bytes(message_type))) => {
+                // None of the messages declare extension ranges.
+                (5, Encoder::new().bytes(1, message_type))
+            }
+            Some((5, _)) => not_found(String::from("No extensions are defined")),
+            Some((7, _)) => {
+                let services = FILES
+                    .iter()
+                    .flat_map(|file| file.services)
+                    .fold(Encoder::new(), |list, service| {
+                        list.message(1, Encoder::new().string(1, service))
+                    });
+                (6, services)
+            }
+            _ => return Err(invalid_message()),
+        };
+        response = response.message(field, message);
+
+        if !call.send(&response.finish()) {
+            break;
+        }
+    }
+    Ok(Status::ok())
+}
+
+fn invalid_message() -> Status {
+    Status::new(INVALID_ARGUMENT, "Malformed request message")
+}
+
+/// A call in progress.
+struct Call<'a, 'b> {
+    stream: &'a ResponseStream<'b>,
+    messages: Messages,
+    /// Whether the response headers have been sent.
+    headers_sent: bool,
+}
+
+impl Call<'_, '_> {
+    /// Returns the only request message of a call without client streaming.
+    ///
+    /// Like the reference implementations, calls with a missing or additional request message
+    /// fail with `INTERNAL`.
+    fn unary_request(&mut self) -> Result<Vec<u8>, Status> {
+        let message = self.messages.next()?;
+        if self.messages.next()?.is_some() {
+            return Err(Status::new(INTERNAL, "Expected a single request message"));
+        }
+        message.ok_or_else(|| Status::new(INTERNAL, "Missing request message"))
+    }
+
+    /// Sends a response message; returns `false` if the stream was closed.
+    fn send(&mut self, message: &[u8]) -> bool {
+        if !self.headers_sent {
+            self.headers_sent = true;
+            if !self
+                .stream
+                .send(StreamFrame::Headers(response_headers(), false))
+            {
+                return false;
+            }
+        }
+
+        // Messages are sent uncompressed, prefixed with their length.
+        let mut data = Vec::with_capacity(message.len() + 5);
+        data.push(0);
+        data.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        data.extend_from_slice(message);
+        self.stream.send(StreamFrame::Data(data, false))
+    }
+
+    /// Ends the call with the status in the trailers.
+    ///
+    /// Calls failing before any message was sent get a trailers-only response.
+    fn finish(self, status: Status) {
+        let mut fields = match self.headers_sent {
+            true => Vec::new(),
+            false => response_headers(),
+        };
+        fields.push((String::from("grpc-status"), status.code.to_string()));
+        if !status.message.is_empty() {
+            fields.push((String::from("grpc-message"), status.message));
+        }
+        self.stream.send(StreamFrame::Headers(fields, true));
+    }
+}
+
+/// Returns the header fields of a gRPC response.
+fn response_headers() -> Vec<(String, String)> {
+    vec![
+        (String::from(":status"), String::from("200")),
+        (
+            String::from("content-type"),
+            String::from("application/grpc"),
+        ),
+    ]
+}
+
+/// The length-prefixed messages of a request body.
+struct Messages {
+    body: Receiver<Vec<u8>>,
+    /// The received data not yet split into messages.
+    buffer: Vec<u8>,
+}
+
+impl Messages {
+    /// Waits for the next message; returns `Ok(None)` once the client ends the stream.
+    fn next(&mut self) -> Result<Option<Vec<u8>>, Status> {
+        loop {
+            if let Some(header) = self.buffer.get(..5) {
+                if header[0] != 0 {
+                    return Err(Status::new(
+                        UNIMPLEMENTED,
+                        "Compressed messages are not supported",
+                    ));
+                }
+                let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+                if length as usize > MAX_BODY_SIZE {
+                    return Err(Status::new(RESOURCE_EXHAUSTED, "Message too large"));
+                }
+                if let Some(message) = self.buffer.get(5..5 + length as usize) {
+                    let message = message.to_vec();
+                    self.buffer.drain(..5 + message.len());
+                    return Ok(Some(message));
+                }
+            }
+
+            match self.body.recv() {
+                Ok(data) => self.buffer.extend_from_slice(&data),
+                Err(_) if self.buffer.is_empty() => return Ok(None),
+                Err(_) => return Err(Status::new(INTERNAL, "Incomplete request message")),
+            }
+        }
+    }
+}
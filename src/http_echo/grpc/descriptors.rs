@@ -0,0 +1,150 @@
+use super::protobuf::Encoder;
+
+// Field types and labels of `FieldDescriptorProto`.
+const TYPE_STRING: u64 = 9;
+const TYPE_BYTES: u64 = 12;
+const TYPE_ENUM: u64 = 14;
+const LABEL_OPTIONAL: u64 = 1;
+
+/// A proto file describing a served service.
+pub struct ProtoFile {
+    /// The file name, e.g. `grpc/health/v1/health.proto`.
+    pub name: &'static str,
+    /// The package of the file.
+    pub package: &'static str,
+    /// The fully qualified names of the services defined in the file.
+    pub services: &'static [&'static str],
+    /// Encodes the `FileDescriptorProto`.
+    pub descriptor: fn() -> Vec<u8>,
+}
+
+impl ProtoFile {
+    /// Returns whether the file defines the fully qualified symbol, e.g. a service,
+    /// method or message.
+    pub fn defines(&self, symbol: &str) -> bool {
+        symbol
+            .strip_prefix(self.package)
+            .is_some_and(|name| name.starts_with('.'))
+    }
+}
+
+/// The proto files of the served services.
+pub const FILES: &[ProtoFile] = &[
+    ProtoFile {
+        name: "grpc/health/v1/health.proto",
+        package: "grpc.health.v1",
+        services: &["grpc.health.v1.Health"],
+        descriptor: health,
+    },
+    ProtoFile {
+        name: "crashie/v1/echo.proto",
+        package: "crashie.v1",
+        services: &["crashie.v1.Echo"],
+        descriptor: echo,
+    },
+];
+
+/// Encodes the descriptor of the standard health checking service.
+fn health() -> Vec<u8> {
+    let request = Encoder::new()
+        .string(1, "HealthCheckRequest")
+        .message(2, field("service", 1, TYPE_STRING, None));
+
+    let serving_status = ["UNKNOWN", "SERVING", "NOT_SERVING", "SERVICE_UNKNOWN"]
+        .iter()
+        .enumerate()
+        .fold(
+            Encoder::new().string(1, "ServingStatus"),
+            |status, (number, name)| {
+                let value = Encoder::new().string(1, name).varint(2, number as u64);
+                status.message(2, value)
+            },
+        );
+    let response = Encoder::new()
+        .string(1, "HealthCheckResponse")
+        .message(
+            2,
+            field(
+                "status",
+                1,
+                TYPE_ENUM,
+                Some(".grpc.health.v1.HealthCheckResponse.ServingStatus"),
+            ),
+        )
+        .message(4, serving_status);
+
+    let request_type = ".grpc.health.v1.HealthCheckRequest";
+    let response_type = ".grpc.health.v1.HealthCheckResponse";
+    let service = Encoder::new()
+        .string(1, "Health")
+        .message(
+            2,
+            method("Check", request_type, response_type, false, false),
+        )
+        .message(2, method("Watch", request_type, response_type, false, true));
+
+    file("grpc/health/v1/health.proto", "grpc.health.v1")
+        .message(4, request)
+        .message(4, response)
+        .message(6, service)
+        .string(12, "proto3")
+        .finish()
+}
+
+/// Encodes the descriptor of the echo service, which returns the request messages unchanged.
+fn echo() -> Vec<u8> {
+    let message = Encoder::new()
+        .string(1, "EchoMessage")
+        .message(2, field("message", 1, TYPE_STRING, None))
+        .message(2, field("payload", 2, TYPE_BYTES, None));
+
+    let message_type = ".crashie.v1.EchoMessage";
+    let service = Encoder::new()
+        .string(1, "Echo")
+        .message(2, method("Echo", message_type, message_type, false, false))
+        .message(
+            2,
+            method("EchoStream", message_type, message_type, true, true),
+        );
+
+    file("crashie/v1/echo.proto", "crashie.v1")
+        .message(4, message)
+        .message(6, service)
+        .string(12, "proto3")
+        .finish()
+}
+
+/// Starts a `FileDescriptorProto`.
+fn file(name: &str, package: &str) -> Encoder {
+    Encoder::new().string(1, name).string(2, package)
+}
+
+/// Encodes a singular `FieldDescriptorProto`.
+fn field(name: &str, number: u64, field_type: u64, type_name: Option<&str>) -> Encoder {
+    let field = Encoder::new()
+        .string(1, name)
+        .varint(3, number)
+        .varint(4, LABEL_OPTIONAL)
+        .varint(5, field_type);
+    let field = match type_name {
+        Some(type_name) => field.string(6, type_name),
+        None => field,
+    };
+    field.string(10, name)
+}
+
+/// Encodes a `MethodDescriptorProto`.
+fn method(
+    name: &str,
+    input_type: &str,
+    output_type: &str,
+    client_streaming: bool,
+    server_streaming: bool,
+) -> Encoder {
+    Encoder::new()
+        .string(1, name)
+        .string(2, input_type)
+        .string(3, output_type)
+        .varint(5, client_streaming as u64)
+        .varint(6, server_streaming as u64)
+}
@@ -0,0 +1,194 @@
+// Wire types.
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// Encodes a protocol buffer message field by field.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    /// Creates an empty message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a varint field, e.g. an integer, boolean or enum value.
+    pub fn varint(mut self, field: u32, value: u64) -> Self {
+        self.key(field, VARINT);
+        write_varint(&mut self.buffer, value);
+        self
+    }
+
+    /// Appends a length-delimited field.
+    pub fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        self.key(field, LENGTH_DELIMITED);
+        write_varint(&mut self.buffer, value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    /// Appends a string field.
+    pub fn string(self, field: u32, value: &str) -> Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Appends an embedded message field.
+    pub fn message(self, field: u32, message: Encoder) -> Self {
+        self.bytes(field, &message.buffer)
+    }
+
+    /// Returns the encoded message.
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        write_varint(&mut self.buffer, (field as u64) << 3 | wire_type);
+    }
+}
+
+/// The value of a decoded field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// A fixed-size value, which none of the decoded messages use.
+    Fixed,
+}
+
+/// Decodes the fields of a message in the order encoded.
+///
+/// Returns `None` if the message is malformed.
+pub fn decode(mut message: &[u8]) -> Option<Vec<(u32, Value<'_>)>> {
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        let field = u32::try_from(key >> 3).ok()?;
+        let value = match key & 0x7 {
+            VARINT => Value::Varint(read_varint(&mut message)?),
+            LENGTH_DELIMITED => {
+                let length = usize::try_from(read_varint(&mut message)?).ok()?;
+                let value = message.get(..length)?;
+                message = &message[length..];
+                Value::Bytes(value)
+            }
+            FIXED64 => {
+                message = message.get(8..)?;
+                Value::Fixed
+            }
+            FIXED32 => {
+                message = message.get(4..)?;
+                Value::Fixed
+            }
+            _ => return None,
+        };
+        fields.push((field, value));
+    }
+    Some(fields)
+}
+
+/// Returns the last string value of the field, as later values take precedence.
+pub fn string_field<'a>(fields: &[(u32, Value<'a>)], field: u32) -> Option<&'a str> {
+    fields.iter().rev().find_map(|(number, value)| match value {
+        Value::Bytes(bytes) if *number == field => std::str::from_utf8(bytes).ok(),
+        _ => None,
+    })
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_varints() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buffer = Vec::new();
+            write_varint(&mut buffer, value);
+            let mut input = buffer.as_slice();
+            assert_eq!(read_varint(&mut input), Some(value));
+            assert!(input.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_varints() {
+        assert_eq!(read_varint(&mut &[][..]), None);
+        assert_eq!(read_varint(&mut &[0x80, 0x80][..]), None);
+        assert_eq!(read_varint(&mut &[0xff; 10][..]), None);
+    }
+
+    #[test]
+    fn decodes_encoded_messages() {
+        let message = Encoder::new()
+            .varint(1, 300)
+            .string(2, "name")
+            .message(3, Encoder::new().varint(1, 1))
+            .string(2, "other")
+            .finish();
+        let fields = decode(&message).unwrap();
+        assert_eq!(
+            fields,
+            [
+                (1, Value::Varint(300)),
+                (2, Value::Bytes(b"name")),
+                (3, Value::Bytes(&[0x08, 0x01])),
+                (2, Value::Bytes(b"other")),
+            ]
+        );
+        assert_eq!(string_field(&fields, 2), Some("other"));
+        assert_eq!(string_field(&fields, 4), None);
+    }
+
+    #[test]
+    fn skips_fixed_size_values() {
+        let message = [0x09, 0, 0, 0, 0, 0, 0, 0, 0, 0x15, 0, 0, 0, 0];
+        assert_eq!(
+            decode(&message),
+            Some(vec![(1, Value::Fixed), (2, Value::Fixed)])
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let message = Encoder::new().string(1, "name").varint(2, 300).finish();
+        for length in 1..message.len() {
+            if length == 6 {
+                // The message ends right after the first field.
+                continue;
+            }
+            assert_eq!(decode(&message[..length]), None, "length {length}");
+        }
+        assert_eq!(decode(&[0x09, 0, 0, 0]), None);
+        assert_eq!(decode(&[0x15, 0, 0]), None);
+    }
+
+    #[test]
+    fn rejects_unsupported_wire_types() {
+        assert_eq!(decode(&[0x0b]), None);
+    }
+}
@@ -0,0 +1,919 @@
+mod frame;
+
+use crate::http_echo::announce::Announcement;
+use crate::http_echo::echo::ConnectionInfo;
+use crate::http_echo::faults::Delivery;
+use crate::http_echo::request::{Request, MAX_BODY_SIZE};
+use crate::http_echo::response::Response;
+use crate::http_echo::streaming::Streaming;
+use crate::http_echo::{respond, HttpEchoConfig, Transport};
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use clap::ValueEnum;
+use frame::*;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufReader, Cursor, Read, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+pub use frame::PREFACE;
+
+/// The maximum number of streams a peer may have open at once, each of which is answered
+/// on a separate thread.
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// How open HTTP/2 connections are notified before the process fails.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Http2Failure {
+    /// Connections are not notified.
+    #[default]
+    None,
+    /// A `GOAWAY` frame is sent on each connection.
+    Goaway,
+    /// Each open stream is reset with a `RST_STREAM` frame.
+    RstStream,
+}
+
+/// A request upgraded from HTTP/1.1 via `Upgrade: h2c`.
+pub struct Upgrade {
+    /// The request, which is answered on stream 1.
+    pub request: Request,
+    /// The settings from the `HTTP2-Settings` header.
+    pub settings: Vec<u8>,
+}
+
+/// Answers the requests received on the streams of a connection.
+pub trait Handler: Sync {
+    /// Responds to a request; called on a separate thread for each stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request, with the part of the body received so far.
+    /// * `body` - The remaining chunks of the body as they arrive, until the client ends the stream.
+    /// * `connection` - Information about the connection.
+    /// * `stream` - The stream to respond on.
+    fn handle(
+        &self,
+        request: Request,
+        body: Receiver<Vec<u8>>,
+        connection: ConnectionInfo,
+        stream: &ResponseStream,
+    );
+}
+
+/// Messages from the reading half and the streams to the writing half of a connection.
+enum Outgoing {
+    /// A frame to send immediately.
+    Frame(Frame),
+    /// The peer's settings, to apply and optionally acknowledge.
+    Settings(Frame, bool),
+    /// The peer opened a stream.
+    Opened(u32),
+    /// The peer reset a stream.
+    Cancelled(u32),
+    /// The peer granted additional flow-control window.
+    WindowUpdate(u32, u32),
+    /// A response frame to send in order on its stream.
+    Stream(u32, StreamFrame),
+}
+
+/// Frames sent in order on a stream.
+pub enum StreamFrame {
+    /// Header fields, optionally ending the stream.
+    Headers(Vec<(String, String)>, bool),
+    /// Data, optionally ending the stream.
+    Data(Vec<u8>, bool),
+    /// Resets the stream with the given error code.
+    Reset(u32),
+}
+
+/// The stream a handler responds on.
+pub struct ResponseStream<'a> {
+    stream_id: u32,
+    sender: Sender<Outgoing>,
+    /// The streams neither reset by the peer nor finished.
+    open: &'a Mutex<HashSet<u32>>,
+}
+
+impl ResponseStream<'_> {
+    /// Sends a frame on the stream; returns `false` if the stream or the connection was closed.
+    pub fn send(&self, frame: StreamFrame) -> bool {
+        self.is_open()
+            && self
+                .sender
+                .send(Outgoing::Stream(self.stream_id, frame))
+                .is_ok()
+    }
+
+    /// Returns whether the stream was neither reset by the peer nor the connection closed.
+    pub fn is_open(&self) -> bool {
+        let open = self.open.lock().expect("Stream lock poisoned");
+        open.contains(&self.stream_id)
+    }
+}
+
+impl Drop for ResponseStream<'_> {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().expect("Stream lock poisoned");
+        open.remove(&self.stream_id);
+    }
+}
+
+/// Serves HTTP/2 on a connection.
+///
+/// # Arguments
+///
+/// * `reader` - The connection, possibly with data already buffered.
+/// * `config` - The configuration of the HTTP echo server.
+/// * `handler` - The handler answering the requests.
+/// * `connection` - Information about the connection.
+/// * `upgrade` - The request upgraded from HTTP/1.1, if any.
+/// * `preface` - The part of the client connection preface not yet consumed.
+pub fn serve<T: Transport, H: Handler>(
+    reader: BufReader<T>,
+    config: &HttpEchoConfig,
+    handler: &H,
+    connection: ConnectionInfo,
+    upgrade: Option<Upgrade>,
+    preface: &[u8],
+) {
+    let buffered = reader.buffer().to_vec();
+    let (input, output) = match reader.into_inner().split() {
+        Ok(halves) => halves,
+        Err(e) => {
+            eprintln!("Failed to split HTTP/2 connection: {e}");
+            return;
+        }
+    };
+    let mut input = BufReader::new(Cursor::new(buffered).chain(input));
+
+    let (sender, receiver) = channel();
+    let announcement = Announcement::register();
+    let open = Mutex::new(HashSet::new());
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut writer = Writer::new(output, config, announcement);
+            if let Err(e) = writer.run(receiver) {
+                eprintln!("Failed to write HTTP/2 frames: {e}");
+            }
+        });
+
+        let mut reader = Reader {
+            handler,
+            connection,
+            sender,
+            decoder: hpack::Decoder::new(),
+            bodies: HashMap::new(),
+            open: &open,
+            last_stream_id: 0,
+        };
+
+        if let Some(upgrade) = upgrade {
+            let settings = Frame::new(SETTINGS, 0, 0, upgrade.settings);
+            if reader.rejects_settings(&settings) {
+                return;
+            }
+            reader.send(Outgoing::Settings(settings, false));
+            reader.last_stream_id = 1;
+            reader.send(Outgoing::Opened(1));
+            reader.dispatch(scope, 1, upgrade.request, true);
+        }
+
+        let mut received = vec![0; preface.len()];
+        if input.read_exact(&mut received).is_err() || received != preface {
+            eprintln!("Invalid HTTP/2 connection preface");
+            reader.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+            return;
+        }
+
+        match reader.run(scope, &mut input) {
+            // TLS peers commonly close the connection without a close_notify alert.
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+            Err(e) => eprintln!("Failed to read HTTP/2 frames: {e}"),
+            Ok(()) => {}
+        }
+
+        // Stop the handlers still responding once the connection is closed.
+        open.lock().expect("Stream lock poisoned").clear();
+    });
+}
+
+/// The reading half of a connection, which assembles requests and dispatches them to streams.
+struct Reader<'a, H: Handler> {
+    handler: &'a H,
+    connection: ConnectionInfo,
+    sender: Sender<Outgoing>,
+    decoder: hpack::Decoder<'static>,
+    /// The request bodies still being received.
+    bodies: HashMap<u32, Sender<Vec<u8>>>,
+    /// The streams neither reset by the peer nor finished.
+    open: &'a Mutex<HashSet<u32>>,
+    /// The highest stream identifier opened by the peer.
+    last_stream_id: u32,
+}
+
+impl<'a, H: Handler> Reader<'a, H> {
+    /// Reads frames until the connection is closed or a connection error occurs.
+    fn run<'scope, R: Read>(
+        &mut self,
+        scope: &'scope thread::Scope<'scope, '_>,
+        input: &mut R,
+    ) -> Result<(), std::io::Error>
+    where
+        'a: 'scope,
+    {
+        // Header blocks spanning CONTINUATION frames, with the stream and END_STREAM flag.
+        let mut header_block: Option<(u32, Vec<u8>, bool)> = None;
+
+        loop {
+            let frame = match Frame::read_from(input, DEFAULT_MAX_FRAME_SIZE) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    self.send(Outgoing::Frame(Frame::goaway(0, FRAME_SIZE_ERROR)));
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+            stall_if_frozen();
+
+            if let Some((stream_id, block, end_stream)) = &mut header_block {
+                if frame.kind != CONTINUATION || frame.stream_id != *stream_id {
+                    self.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+                    return Ok(());
+                }
+                block.extend_from_slice(&frame.payload);
+                if frame.has_flag(END_HEADERS) {
+                    let (stream_id, block, end_stream) = (*stream_id, block.clone(), *end_stream);
+                    header_block = None;
+                    if !self.receive_headers(scope, stream_id, &block, end_stream) {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+
+            match frame.kind {
+                HEADERS => {
+                    let Some(block) = frame.data() else {
+                        self.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+                        return Ok(());
+                    };
+                    let end_stream = frame.has_flag(END_STREAM);
+                    if !frame.has_flag(END_HEADERS) {
+                        header_block = Some((frame.stream_id, block.to_vec(), end_stream));
+                    } else if !self.receive_headers(scope, frame.stream_id, block, end_stream) {
+                        return Ok(());
+                    }
+                }
+                DATA if frame.stream_id == 0 => {
+                    self.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+                    return Ok(());
+                }
+                DATA => self.receive_data(&frame),
+                SETTINGS if !frame.has_flag(ACK) => {
+                    if self.rejects_settings(&frame) {
+                        return Ok(());
+                    }
+                    self.send(Outgoing::Settings(frame, true));
+                }
+                PING if !frame.has_flag(ACK) => {
+                    self.send(Outgoing::Frame(Frame::new(PING, ACK, 0, frame.payload)));
+                }
+                WINDOW_UPDATE => {
+                    let increment = frame.first_u32().unwrap_or(0);
+                    self.send(Outgoing::WindowUpdate(frame.stream_id, increment));
+                }
+                RST_STREAM => {
+                    self.bodies.remove(&frame.stream_id);
+                    self.open
+                        .lock()
+                        .expect("Stream lock poisoned")
+                        .remove(&frame.stream_id);
+                    self.send(Outgoing::Cancelled(frame.stream_id));
+                }
+                CONTINUATION => {
+                    self.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+                    return Ok(());
+                }
+                // Priorities, acknowledgements, GOAWAY and unknown frames need no action.
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles a complete header block and returns whether the connection is still usable.
+    fn receive_headers<'scope>(
+        &mut self,
+        scope: &'scope thread::Scope<'scope, '_>,
+        stream_id: u32,
+        block: &[u8],
+        end_stream: bool,
+    ) -> bool
+    where
+        'a: 'scope,
+    {
+        // The header block must be decoded even if the stream is refused, to keep
+        // the decoder state in sync.
+        let fields = match self.decoder.decode(block) {
+            Ok(fields) => fields,
+            Err(e) => {
+                eprintln!("Failed to decode HTTP/2 headers: {e:?}");
+                self.send(Outgoing::Frame(Frame::goaway(0, COMPRESSION_ERROR)));
+                return false;
+            }
+        };
+
+        // Trailers of a request complete it; their fields are not reflected.
+        if self.bodies.contains_key(&stream_id) {
+            if end_stream {
+                self.bodies.remove(&stream_id);
+            }
+            return true;
+        }
+
+        // Clients open streams with increasing odd identifiers, which cannot be reused.
+        if stream_id & 1 == 0 || stream_id <= self.last_stream_id {
+            self.send(Outgoing::Frame(Frame::goaway(0, PROTOCOL_ERROR)));
+            return false;
+        }
+        self.last_stream_id = stream_id;
+        self.send(Outgoing::Opened(stream_id));
+
+        let concurrent = self.open.lock().expect("Stream lock poisoned").len();
+        if is_draining() || concurrent >= MAX_CONCURRENT_STREAMS as usize {
+            self.send(Outgoing::Stream(
+                stream_id,
+                StreamFrame::Reset(REFUSED_STREAM),
+            ));
+            return true;
+        }
+
+        let Some(request) = to_request(fields) else {
+            self.send(Outgoing::Stream(
+                stream_id,
+                StreamFrame::Reset(PROTOCOL_ERROR),
+            ));
+            return true;
+        };
+
+        self.dispatch(scope, stream_id, request, end_stream);
+        true
+    }
+
+    /// Passes the data on to the handler receiving the request body.
+    fn receive_data(&mut self, frame: &Frame) {
+        // Replenish the flow-control windows right away, including any padding.
+        let length = frame.payload.len() as u32;
+        let end_stream = frame.has_flag(END_STREAM);
+        if length > 0 {
+            self.send(Outgoing::Frame(Frame::window_update(0, length)));
+            if !end_stream {
+                self.send(Outgoing::Frame(Frame::window_update(
+                    frame.stream_id,
+                    length,
+                )));
+            }
+        }
+
+        if let Some(body) = self.bodies.get(&frame.stream_id) {
+            let data = frame.data().unwrap_or_default();
+            let _ = body.send(data.to_vec());
+        }
+        if end_stream {
+            self.bodies.remove(&frame.stream_id);
+        }
+    }
+
+    /// Responds to the request on a separate thread.
+    ///
+    /// Unless the stream has ended, the request body is passed on as it arrives.
+    fn dispatch<'scope>(
+        &mut self,
+        scope: &'scope thread::Scope<'scope, '_>,
+        stream_id: u32,
+        request: Request,
+        end_stream: bool,
+    ) where
+        'a: 'scope,
+    {
+        let (body_sender, body) = channel();
+        if !end_stream {
+            self.bodies.insert(stream_id, body_sender);
+        }
+
+        self.connection.request_number += 1;
+        let connection = self.connection.clone();
+        let handler = self.handler;
+        self.open
+            .lock()
+            .expect("Stream lock poisoned")
+            .insert(stream_id);
+        let stream = ResponseStream {
+            stream_id,
+            sender: self.sender.clone(),
+            open: self.open,
+        };
+        scope.spawn(move || handler.handle(request, body, connection, &stream));
+    }
+
+    /// Returns whether the peer's settings are invalid, after sending the `GOAWAY` frame
+    /// for the connection error.
+    fn rejects_settings(&self, frame: &Frame) -> bool {
+        match frame.settings_error() {
+            Some(error_code) => {
+                self.send(Outgoing::Frame(Frame::goaway(0, error_code)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends a message to the writing half; errors mean the connection is closing.
+    fn send(&self, message: Outgoing) {
+        let _ = self.sender.send(message);
+    }
+}
+
+impl Handler for HttpEchoConfig {
+    fn handle(
+        &self,
+        mut request: Request,
+        body: Receiver<Vec<u8>>,
+        connection: ConnectionInfo,
+        stream: &ResponseStream,
+    ) {
+        // Bodies exceeding the maximum size are received completely, but rejected.
+        let mut too_large = false;
+        for data in body {
+            too_large |= request.body.len() + data.len() > MAX_BODY_SIZE;
+            if !too_large {
+                request.body.extend_from_slice(&data);
+            }
+        }
+        if too_large {
+            let fields = vec![(String::from(":status"), String::from("413"))];
+            stream.send(StreamFrame::Headers(fields, true));
+            return;
+        }
+
+        let head_only = request.method == "HEAD";
+        if let Some(streaming) = Streaming::from_request(self, &request) {
+            send_streaming(streaming, head_only, stream);
+            return;
+        }
+
+        let (response, delivery) = respond(self, &request, &connection);
+        for frame in response_frames(response, delivery, head_only) {
+            if !stream.send(frame) {
+                return;
+            }
+        }
+    }
+}
+
+/// Converts decoded header fields into a request.
+///
+/// Returns `None` if mandatory pseudo-header fields are missing.
+fn to_request(fields: Vec<(Vec<u8>, Vec<u8>)>) -> Option<Request> {
+    let mut method = None;
+    let mut target = None;
+    let mut authority = None;
+    let mut headers = Vec::new();
+    for (name, value) in fields {
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let value = String::from_utf8_lossy(&value).into_owned();
+        match name.as_str() {
+            ":method" => method = Some(value),
+            ":path" => target = Some(value),
+            ":authority" => authority = Some(value),
+            name if name.starts_with(':') => {}
+            _ => headers.push((name, value)),
+        }
+    }
+
+    // Reflect the authority as the Host header, as HTTP/1.1 clients would send it.
+    let mut request = Request {
+        method: method?,
+        target: target?,
+        version: String::from("HTTP/2.0"),
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(authority) = authority.filter(|_| request.header("Host").is_none()) {
+        request.headers.insert(0, (String::from("host"), authority));
+    }
+    Some(request)
+}
+
+/// Translates a response and its delivery into the frames of a stream.
+///
+/// Dropped and truncated responses reset the stream, as closing the connection
+/// would affect other streams.
+fn response_frames(response: Response, delivery: Delivery, head_only: bool) -> Vec<StreamFrame> {
+    let fields = |content_length: usize| header_block(&response, Some(content_length));
+
+    let body = if head_only {
+        Vec::new()
+    } else {
+        response.body.clone()
+    };
+    let length = response.body.len();
+    match delivery {
+        Delivery::Drop => vec![StreamFrame::Reset(INTERNAL_ERROR)],
+        Delivery::Truncate(fraction) => {
+            let end = (body.len() as f64 * fraction) as usize;
+            vec![
+                StreamFrame::Headers(fields(length), false),
+                StreamFrame::Data(body[..end].to_vec(), false),
+                StreamFrame::Reset(INTERNAL_ERROR),
+            ]
+        }
+        Delivery::Complete | Delivery::LengthMismatch(_) => {
+            let content_length = match delivery {
+                Delivery::LengthMismatch(length) => length,
+                _ => length,
+            };
+            if body.is_empty() {
+                vec![StreamFrame::Headers(fields(content_length), true)]
+            } else {
+                vec![
+                    StreamFrame::Headers(fields(content_length), false),
+                    StreamFrame::Data(body, true),
+                ]
+            }
+        }
+    }
+}
+
+/// Sends a streaming response until it ends or the stream is closed.
+fn send_streaming(mut streaming: Streaming, head_only: bool, stream: &ResponseStream) {
+    let fields = header_block(&streaming.response(), None);
+    if stream.send(StreamFrame::Headers(fields, head_only)) && !head_only {
+        while let Some(message) = streaming.next_message() {
+            if !stream.send(StreamFrame::Data(message, false)) {
+                return;
+            }
+        }
+        stream.send(StreamFrame::Data(Vec::new(), true));
+    }
+}
+
+/// Returns the header fields of a response, without those not allowed in HTTP/2.
+fn header_block(response: &Response, content_length: Option<usize>) -> Vec<(String, String)> {
+    let mut fields = vec![(String::from(":status"), response.status.to_string())];
+    fields.extend(
+        response
+            .header_fields(content_length)
+            .into_iter()
+            .filter(|(name, _)| !is_connection_specific(name)),
+    );
+    fields
+}
+
+/// Returns whether the header is specific to HTTP/1.1 connections and not allowed in HTTP/2.
+fn is_connection_specific(name: &str) -> bool {
+    [
+        "connection",
+        "keep-alive",
+        "proxy-connection",
+        "transfer-encoding",
+        "upgrade",
+    ]
+    .iter()
+    .any(|header| name.eq_ignore_ascii_case(header))
+}
+
+/// The writing half of a connection, which owns the flow-control state.
+struct Writer<'a, W: Write> {
+    output: W,
+    config: &'a HttpEchoConfig,
+    /// The send window of the connection.
+    window: i64,
+    /// The send windows of the open streams.
+    stream_windows: HashMap<u32, i64>,
+    /// The initial send window of new streams.
+    initial_window: i64,
+    /// The maximum frame size accepted by the peer.
+    max_frame_size: usize,
+    /// The frames waiting to be sent on each stream.
+    queues: BTreeMap<u32, VecDeque<StreamFrame>>,
+    /// The highest stream identifier opened by the peer.
+    last_stream_id: u32,
+    /// Whether a `GOAWAY` frame has been sent.
+    goaway_sent: bool,
+    /// The announcement of the failure to the peer.
+    announcement: Announcement,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    fn new(output: W, config: &'a HttpEchoConfig, announcement: Announcement) -> Self {
+        Self {
+            output,
+            config,
+            window: DEFAULT_WINDOW_SIZE,
+            stream_windows: HashMap::new(),
+            initial_window: DEFAULT_WINDOW_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            queues: BTreeMap::new(),
+            last_stream_id: 0,
+            goaway_sent: false,
+            announcement,
+        }
+    }
+
+    /// Writes frames until all senders have disconnected.
+    fn run(&mut self, receiver: Receiver<Outgoing>) -> Result<(), std::io::Error> {
+        // The server connection preface.
+        Frame::settings(&[(SETTINGS_MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS)])
+            .write_to(&mut self.output)?;
+
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(message) => self.handle(message)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            self.flush_queues()?;
+
+            if is_draining() && !self.goaway_sent {
+                self.goaway_sent = true;
+                Frame::goaway(self.last_stream_id, NO_ERROR).write_to(&mut self.output)?;
+            }
+            if self.announcement.is_due() {
+                self.announce()?;
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Outgoing) -> Result<(), std::io::Error> {
+        match message {
+            Outgoing::Frame(frame) => frame.write_to(&mut self.output)?,
+            Outgoing::Settings(frame, acknowledge) => {
+                for (id, value) in frame.setting_values() {
+                    match id {
+                        SETTINGS_INITIAL_WINDOW_SIZE => {
+                            let delta = value as i64 - self.initial_window;
+                            self.initial_window = value as i64;
+                            for window in self.stream_windows.values_mut() {
+                                *window += delta;
+                            }
+                        }
+                        SETTINGS_MAX_FRAME_SIZE => self.max_frame_size = value as usize,
+                        _ => {}
+                    }
+                }
+                if acknowledge {
+                    Frame::new(SETTINGS, ACK, 0, Vec::new()).write_to(&mut self.output)?;
+                }
+            }
+            Outgoing::Opened(stream_id) => {
+                self.last_stream_id = self.last_stream_id.max(stream_id);
+                self.stream_windows.insert(stream_id, self.initial_window);
+            }
+            Outgoing::Cancelled(stream_id) => {
+                self.queues.remove(&stream_id);
+                self.stream_windows.remove(&stream_id);
+            }
+            Outgoing::WindowUpdate(0, increment) => self.window += increment as i64,
+            Outgoing::WindowUpdate(stream_id, increment) => {
+                if let Some(window) = self.stream_windows.get_mut(&stream_id) {
+                    *window += increment as i64;
+                }
+            }
+            Outgoing::Stream(stream_id, frame) => {
+                // Frames of streams reset by the peer are discarded.
+                if self.stream_windows.contains_key(&stream_id) {
+                    self.queues.entry(stream_id).or_default().push_back(frame);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the queued frames as far as the flow-control windows allow.
+    fn flush_queues(&mut self) -> Result<(), std::io::Error> {
+        let stream_ids: Vec<u32> = self.queues.keys().copied().collect();
+        for stream_id in stream_ids {
+            while let Some(frame) = self.queues.get_mut(&stream_id).and_then(|q| q.pop_front()) {
+                let closed = match frame {
+                    StreamFrame::Headers(fields, end_stream) => {
+                        self.write_headers(stream_id, &fields, end_stream)?;
+                        end_stream
+                    }
+                    StreamFrame::Data(mut data, end_stream) => {
+                        let window = self.window.min(self.stream_windows[&stream_id]);
+                        let length = data.len().min(window.max(0) as usize);
+                        let complete = length == data.len();
+                        if data.is_empty() {
+                            let flags = if end_stream { END_STREAM } else { 0 };
+                            Frame::new(DATA, flags, stream_id, Vec::new())
+                                .write_to(&mut self.output)?;
+                        }
+                        let mut chunks = data[..length].chunks(self.max_frame_size).peekable();
+                        while let Some(chunk) = chunks.next() {
+                            let last = complete && chunks.peek().is_none();
+                            let flags = if last && end_stream { END_STREAM } else { 0 };
+                            Frame::new(DATA, flags, stream_id, chunk.to_vec())
+                                .write_to(&mut self.output)?;
+                        }
+                        self.window -= length as i64;
+                        if let Some(window) = self.stream_windows.get_mut(&stream_id) {
+                            *window -= length as i64;
+                        }
+
+                        if !complete {
+                            // Wait for the peer to open the window.
+                            data.drain(..length);
+                            if let Some(queue) = self.queues.get_mut(&stream_id) {
+                                queue.push_front(StreamFrame::Data(data, end_stream));
+                            }
+                            break;
+                        }
+                        end_stream
+                    }
+                    StreamFrame::Reset(error_code) => {
+                        Frame::rst_stream(stream_id, error_code).write_to(&mut self.output)?;
+                        true
+                    }
+                };
+
+                if closed {
+                    self.queues.remove(&stream_id);
+                    self.stream_windows.remove(&stream_id);
+                    break;
+                }
+            }
+            if self.queues.get(&stream_id).is_some_and(VecDeque::is_empty) {
+                self.queues.remove(&stream_id);
+            }
+        }
+        self.output.flush()
+    }
+
+    /// Writes a header block, split into `CONTINUATION` frames as needed.
+    fn write_headers(
+        &mut self,
+        stream_id: u32,
+        fields: &[(String, String)],
+        end_stream: bool,
+    ) -> Result<(), std::io::Error> {
+        let block = encode_headers(fields);
+        let mut chunks = block.chunks(self.max_frame_size).peekable();
+        let mut kind = HEADERS;
+        let mut flags = if end_stream { END_STREAM } else { 0 };
+        loop {
+            let chunk = chunks.next().unwrap_or_default();
+            if chunks.peek().is_none() {
+                flags |= END_HEADERS;
+            }
+            Frame::new(kind, flags, stream_id, chunk.to_vec()).write_to(&mut self.output)?;
+            if chunks.peek().is_none() {
+                return Ok(());
+            }
+            kind = CONTINUATION;
+            flags = 0;
+        }
+    }
+
+    /// Notifies the peer of the imminent failure as configured.
+    fn announce(&mut self) -> Result<(), std::io::Error> {
+        let error_code = self.config.http2_failure_error_code;
+        match self.config.http2_on_failure {
+            Http2Failure::None => {}
+            Http2Failure::Goaway => {
+                Frame::goaway(self.last_stream_id, error_code).write_to(&mut self.output)?;
+            }
+            Http2Failure::RstStream => {
+                let stream_ids: Vec<u32> = self.stream_windows.keys().copied().collect();
+                for stream_id in stream_ids {
+                    Frame::rst_stream(stream_id, error_code).write_to(&mut self.output)?;
+                    self.queues.remove(&stream_id);
+                    self.stream_windows.remove(&stream_id);
+                }
+            }
+        }
+        self.output.flush()?;
+        self.announcement.complete();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Holds each stream open until it is closed, such that the streams stay concurrent.
+    struct HoldOpen;
+
+    impl Handler for HoldOpen {
+        fn handle(
+            &self,
+            _: Request,
+            _: Receiver<Vec<u8>>,
+            _: ConnectionInfo,
+            stream: &ResponseStream,
+        ) {
+            while stream.is_open() {
+                sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Runs a reader on the frames and returns the messages sent to the writing half.
+    fn read_frames(frames: &[Frame]) -> Vec<Outgoing> {
+        let mut input = Vec::new();
+        for frame in frames {
+            frame.write_to(&mut input).unwrap();
+        }
+        let (sender, receiver) = channel();
+        let open = Mutex::new(HashSet::new());
+        thread::scope(|scope| {
+            let mut reader = Reader {
+                handler: &HoldOpen,
+                connection: ConnectionInfo {
+                    peer_addr: None,
+                    local_addr: None,
+                    tls: None,
+                    request_number: 0,
+                },
+                sender,
+                decoder: hpack::Decoder::new(),
+                bodies: HashMap::new(),
+                open: &open,
+                last_stream_id: 0,
+            };
+            reader.run(scope, &mut input.as_slice()).unwrap();
+            open.lock().unwrap().clear();
+        });
+        receiver.into_iter().collect()
+    }
+
+    fn request(stream_id: u32) -> Frame {
+        let fields = [(":method", "GET"), (":scheme", "http"), (":path", "/")]
+            .map(|(name, value)| (String::from(name), String::from(value)));
+        let block = encode_headers(&fields);
+        Frame::new(HEADERS, END_HEADERS | END_STREAM, stream_id, block)
+    }
+
+    fn goaway_code(messages: &[Outgoing]) -> Option<u32> {
+        messages.iter().find_map(|message| match message {
+            Outgoing::Frame(frame) if frame.kind == GOAWAY => {
+                Some(u32::from_be_bytes(frame.payload[4..8].try_into().unwrap()))
+            }
+            _ => None,
+        })
+    }
+
+    fn resets(messages: &[Outgoing]) -> Vec<(u32, u32)> {
+        let resets = messages.iter().filter_map(|message| match message {
+            Outgoing::Stream(stream_id, StreamFrame::Reset(code)) => Some((*stream_id, *code)),
+            _ => None,
+        });
+        resets.collect()
+    }
+
+    #[test]
+    fn accepts_increasing_stream_ids() {
+        let messages = read_frames(&[request(1), request(3), request(7)]);
+        assert_eq!(goaway_code(&messages), None);
+        assert!(resets(&messages).is_empty());
+    }
+
+    #[test]
+    fn rejects_reused_stream_ids() {
+        let messages = read_frames(&[request(1), request(1)]);
+        assert_eq!(goaway_code(&messages), Some(PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn rejects_decreasing_stream_ids() {
+        let messages = read_frames(&[request(5), request(3)]);
+        assert_eq!(goaway_code(&messages), Some(PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn rejects_even_stream_ids() {
+        let messages = read_frames(&[request(2)]);
+        assert_eq!(goaway_code(&messages), Some(PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn rejects_data_on_connection_stream() {
+        let messages = read_frames(&[Frame::new(DATA, 0, 0, b"data".to_vec())]);
+        assert_eq!(goaway_code(&messages), Some(PROTOCOL_ERROR));
+    }
+
+    #[test]
+    fn refuses_streams_over_concurrency_limit() {
+        let frames: Vec<Frame> = (0..=MAX_CONCURRENT_STREAMS)
+            .map(|i| request(2 * i + 1))
+            .collect();
+        let messages = read_frames(&frames);
+        assert_eq!(goaway_code(&messages), None);
+        let last = 2 * MAX_CONCURRENT_STREAMS + 1;
+        assert_eq!(resets(&messages), [(last, REFUSED_STREAM)]);
+    }
+}
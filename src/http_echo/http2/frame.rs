@@ -0,0 +1,346 @@
+use std::io::{Read, Write};
+
+/// The client connection preface.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The size of the frame header.
+const HEADER_SIZE: usize = 9;
+
+/// The default maximum frame payload size.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+/// The largest maximum frame payload size a peer may announce.
+const MAX_FRAME_SIZE_LIMIT: usize = 16_777_215;
+
+/// The largest flow-control window size.
+const MAX_WINDOW_SIZE: u32 = 0x7fff_ffff;
+
+/// The default flow-control window size.
+pub const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+
+// Frame types.
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const WINDOW_UPDATE: u8 = 0x8;
+pub const CONTINUATION: u8 = 0x9;
+
+// Frame flags.
+pub const END_STREAM: u8 = 0x1;
+pub const ACK: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const PADDED: u8 = 0x8;
+pub const PRIORITY: u8 = 0x20;
+
+// Settings identifiers.
+pub const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+// Error codes.
+pub const NO_ERROR: u32 = 0x0;
+pub const PROTOCOL_ERROR: u32 = 0x1;
+pub const INTERNAL_ERROR: u32 = 0x2;
+pub const FLOW_CONTROL_ERROR: u32 = 0x3;
+pub const FRAME_SIZE_ERROR: u32 = 0x6;
+pub const REFUSED_STREAM: u32 = 0x7;
+pub const COMPRESSION_ERROR: u32 = 0x9;
+
+/// An HTTP/2 frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The frame type.
+    pub kind: u8,
+    /// The frame flags.
+    pub flags: u8,
+    /// The stream identifier, or zero for connection frames.
+    pub stream_id: u32,
+    /// The frame payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a frame.
+    pub fn new(kind: u8, flags: u8, stream_id: u32, payload: Vec<u8>) -> Self {
+        Self {
+            kind,
+            flags,
+            stream_id,
+            payload,
+        }
+    }
+
+    /// Creates a `SETTINGS` frame from identifier and value pairs.
+    pub fn settings(settings: &[(u16, u32)]) -> Self {
+        let mut payload = Vec::with_capacity(settings.len() * 6);
+        for (id, value) in settings {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        Self::new(SETTINGS, 0, 0, payload)
+    }
+
+    /// Creates a `RST_STREAM` frame.
+    pub fn rst_stream(stream_id: u32, error_code: u32) -> Self {
+        Self::new(RST_STREAM, 0, stream_id, error_code.to_be_bytes().to_vec())
+    }
+
+    /// Creates a `GOAWAY` frame.
+    pub fn goaway(last_stream_id: u32, error_code: u32) -> Self {
+        let mut payload = last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error_code.to_be_bytes());
+        Self::new(GOAWAY, 0, 0, payload)
+    }
+
+    /// Creates a `WINDOW_UPDATE` frame.
+    pub fn window_update(stream_id: u32, increment: u32) -> Self {
+        Self::new(
+            WINDOW_UPDATE,
+            0,
+            stream_id,
+            increment.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// Returns whether the flag is set.
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Returns the payload without padding and priority information.
+    ///
+    /// Returns `None` if the padding exceeds the payload.
+    pub fn data(&self) -> Option<&[u8]> {
+        let mut payload = self.payload.as_slice();
+        let mut padding = 0;
+        if self.has_flag(PADDED) {
+            padding = *payload.first()? as usize;
+            payload = &payload[1..];
+        }
+        if self.kind == HEADERS && self.has_flag(PRIORITY) {
+            payload = payload.get(5..)?;
+        }
+        payload.get(..payload.len().checked_sub(padding)?)
+    }
+
+    /// Parses the identifier and value pairs of a `SETTINGS` frame.
+    pub fn setting_values(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        self.payload.chunks_exact(6).map(|setting| {
+            (
+                u16::from_be_bytes([setting[0], setting[1]]),
+                u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]),
+            )
+        })
+    }
+
+    /// Returns the error code of the connection error caused by an invalid setting, if any.
+    pub fn settings_error(&self) -> Option<u32> {
+        self.setting_values().find_map(|(id, value)| match id {
+            SETTINGS_INITIAL_WINDOW_SIZE if value > MAX_WINDOW_SIZE => Some(FLOW_CONTROL_ERROR),
+            SETTINGS_MAX_FRAME_SIZE
+                if !(DEFAULT_MAX_FRAME_SIZE..=MAX_FRAME_SIZE_LIMIT).contains(&(value as usize)) =>
+            {
+                Some(PROTOCOL_ERROR)
+            }
+            _ => None,
+        })
+    }
+
+    /// Reads the 32-bit value at the start of the payload, ignoring the reserved bit.
+    pub fn first_u32(&self) -> Option<u32> {
+        let bytes = self.payload.get(..4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) & 0x7fff_ffff)
+    }
+
+    /// Reads a frame from the stream.
+    ///
+    /// Returns `Ok(None)` if the stream was closed before a frame started.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an `std::io::Error` if reading fails, or with
+    /// `ErrorKind::InvalidData` if the frame exceeds the maximum size.
+    pub fn read_from<R: Read>(
+        stream: &mut R,
+        max_size: usize,
+    ) -> Result<Option<Self>, std::io::Error> {
+        let mut header = [0; HEADER_SIZE];
+        match stream.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => stream.read_exact(&mut header[1..])?,
+        }
+
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        if length > max_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("HTTP/2 frame of {length} bytes exceeds the maximum size"),
+            ));
+        }
+
+        let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+        let mut payload = vec![0; length];
+        stream.read_exact(&mut payload)?;
+        Ok(Some(Self::new(
+            header[3],
+            header[4],
+            stream_id & 0x7fff_ffff,
+            payload,
+        )))
+    }
+
+    /// Writes the frame to the stream.
+    pub fn write_to<W: Write>(&self, stream: &mut W) -> Result<(), std::io::Error> {
+        let length = (self.payload.len() as u32).to_be_bytes();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(&length[1..]);
+        bytes.push(self.kind);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.stream_id.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        stream.write_all(&bytes)
+    }
+}
+
+/// Encodes header fields as an HPACK header block.
+///
+/// All fields are sent as literals without indexing, such that the encoder
+/// needs no dynamic table.
+pub fn encode_headers(fields: &[(String, String)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for (name, value) in fields {
+        block.push(0x00);
+        encode_string(&mut block, name.to_ascii_lowercase().as_bytes());
+        encode_string(&mut block, value.as_bytes());
+    }
+    block
+}
+
+/// Encodes a string literal without Huffman coding.
+fn encode_string(block: &mut Vec<u8>, value: &[u8]) {
+    // Integer representation with a 7-bit prefix (RFC 7541, section 5.1).
+    let mut length = value.len();
+    if length < 0x7f {
+        block.push(length as u8);
+    } else {
+        block.push(0x7f);
+        length -= 0x7f;
+        while length >= 0x80 {
+            block.push((length % 0x80) as u8 | 0x80);
+            length /= 0x80;
+        }
+        block.push(length as u8);
+    }
+    block.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_and_reads_frames() {
+        let frame = Frame::new(HEADERS, END_HEADERS, 3, b"hello".to_vec());
+        let mut bytes = Vec::new();
+        frame.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            &bytes[..HEADER_SIZE],
+            &[0, 0, 5, HEADERS, END_HEADERS, 0, 0, 0, 3]
+        );
+
+        let mut stream = bytes.as_slice();
+        assert_eq!(Frame::read_from(&mut stream, 5).unwrap(), Some(frame));
+        assert_eq!(Frame::read_from(&mut stream, 5).unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_reserved_stream_id_bit() {
+        let bytes = [0, 0, 0, DATA, 0, 0x80, 0, 0, 1];
+        let frame = Frame::read_from(&mut bytes.as_slice(), 0).unwrap().unwrap();
+        assert_eq!(frame.stream_id, 1);
+    }
+
+    #[test]
+    fn rejects_frames_over_max_size() {
+        let bytes = [0, 0, 6, DATA, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        let error = Frame::read_from(&mut bytes.as_slice(), 5).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let bytes = [0, 0, 5, DATA, 0, 0, 0, 0, 1, b'h'];
+        let error = Frame::read_from(&mut bytes.as_slice(), 5).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn strips_padding_and_priority() {
+        let frame = Frame::new(DATA, PADDED, 1, b"\x02data\0\0".to_vec());
+        assert_eq!(frame.data(), Some(&b"data"[..]));
+
+        let frame = Frame::new(
+            HEADERS,
+            PADDED | PRIORITY,
+            1,
+            b"\x01\0\0\0\0\x10block\0".to_vec(),
+        );
+        assert_eq!(frame.data(), Some(&b"block"[..]));
+    }
+
+    #[test]
+    fn rejects_padding_exceeding_payload() {
+        assert_eq!(
+            Frame::new(DATA, PADDED, 1, b"\x05data".to_vec()).data(),
+            None
+        );
+        assert_eq!(Frame::new(DATA, PADDED, 1, Vec::new()).data(), None);
+        assert_eq!(Frame::new(HEADERS, PRIORITY, 1, vec![0; 4]).data(), None);
+    }
+
+    #[test]
+    fn parses_setting_values() {
+        let frame = Frame::settings(&[
+            (SETTINGS_INITIAL_WINDOW_SIZE, 1),
+            (SETTINGS_MAX_FRAME_SIZE, 2),
+        ]);
+        let mut payload = frame.payload.clone();
+        // A trailing partial setting is ignored.
+        payload.push(0);
+        let frame = Frame::new(SETTINGS, 0, 0, payload);
+        let values: Vec<_> = frame.setting_values().collect();
+        assert_eq!(
+            values,
+            [
+                (SETTINGS_INITIAL_WINDOW_SIZE, 1),
+                (SETTINGS_MAX_FRAME_SIZE, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_valid_settings() {
+        let frame = Frame::settings(&[
+            (SETTINGS_INITIAL_WINDOW_SIZE, MAX_WINDOW_SIZE),
+            (SETTINGS_MAX_FRAME_SIZE, MAX_FRAME_SIZE_LIMIT as u32),
+        ]);
+        assert_eq!(frame.settings_error(), None);
+    }
+
+    #[test]
+    fn rejects_max_frame_size_out_of_range() {
+        for value in [0, 16_383, 16_777_216] {
+            let frame = Frame::settings(&[(SETTINGS_MAX_FRAME_SIZE, value)]);
+            assert_eq!(frame.settings_error(), Some(PROTOCOL_ERROR));
+        }
+    }
+
+    #[test]
+    fn rejects_initial_window_size_over_limit() {
+        let frame = Frame::settings(&[(SETTINGS_INITIAL_WINDOW_SIZE, 0x8000_0000)]);
+        assert_eq!(frame.settings_error(), Some(FLOW_CONTROL_ERROR));
+    }
+}
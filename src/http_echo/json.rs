@@ -0,0 +1,101 @@
+use std::fmt::{Display, Formatter, Write};
+
+/// A minimal JSON value, used to render responses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Creates a JSON string.
+    pub fn string<S: Into<String>>(value: S) -> Self {
+        Json::String(value.into())
+    }
+
+    /// Renders the value with two spaces of indentation per level.
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0)
+            .expect("writing to a string cannot fail");
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, indent: usize) -> std::fmt::Result {
+        match self {
+            Json::Array(values) if !values.is_empty() => {
+                output.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    write!(output, "{:width$}", "", width = indent + 2)?;
+                    value.write_pretty(output, indent + 2)?;
+                    output.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+                }
+                write!(output, "{:width$}]", "", width = indent)
+            }
+            Json::Object(entries) if !entries.is_empty() => {
+                output.push_str("{\n");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    write!(output, "{:width$}", "", width = indent + 2)?;
+                    write_escaped(output, key)?;
+                    output.push_str(": ");
+                    value.write_pretty(output, indent + 2)?;
+                    output.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+                }
+                write!(output, "{:width$}}}", "", width = indent)
+            }
+            value => write!(output, "{value}"),
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Number(value) if value.is_finite() => write!(f, "{value}"),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(value) => write_escaped(f, value),
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(entries) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Writes a string as a quoted and escaped JSON string.
+fn write_escaped<W: Write>(output: &mut W, value: &str) -> std::fmt::Result {
+    output.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => output.write_str("\\\"")?,
+            '\\' => output.write_str("\\\\")?,
+            '\n' => output.write_str("\\n")?,
+            '\r' => output.write_str("\\r")?,
+            '\t' => output.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32)?,
+            c => output.write_char(c)?,
+        }
+    }
+    output.write_char('"')
+}
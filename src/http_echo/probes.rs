@@ -0,0 +1,119 @@
+use crate::lifecycle::{is_draining, scheduled_lifetime, time_until_failure, uptime};
+use clap::ValueEnum;
+use std::time::Duration;
+
+/// How the liveness probe fails.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LivenessFailure {
+    /// Respond with `500 Internal Server Error`.
+    #[value(name = "500")]
+    Status500,
+    /// Respond with `503 Service Unavailable`.
+    #[default]
+    #[value(name = "503")]
+    Status503,
+    /// Stop answering liveness probe requests.
+    Hang,
+}
+
+/// The point in time after which the liveness probe fails.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LivenessFailAfter {
+    /// The given time after startup.
+    Elapsed(Duration),
+    /// The given fraction of the time between startup and the scheduled failure.
+    Fraction(f64),
+}
+
+/// The response to a probe request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProbeResponse {
+    /// Respond with the given status code.
+    Status(u16),
+    /// Do not respond at all.
+    Hang,
+}
+
+/// The paths and timelines of the liveness, readiness and startup probes.
+#[derive(Debug, Clone)]
+pub struct Probes {
+    /// The path of the liveness probe.
+    pub liveness_path: String,
+    /// The path of the readiness probe.
+    pub readiness_path: String,
+    /// The path of the startup probe.
+    pub startup_path: String,
+    /// The time after startup during which the startup probe fails.
+    pub started_after: Duration,
+    /// The time after startup during which the readiness probe fails.
+    pub ready_after: Duration,
+    /// The time before the scheduled failure during which the readiness probe fails.
+    pub unready_before: Duration,
+    /// The optional point in time after which the liveness probe fails.
+    pub liveness_fail_after: Option<LivenessFailAfter>,
+    /// How the liveness probe fails.
+    pub liveness_failure: LivenessFailure,
+}
+
+impl Probes {
+    /// Returns whether the path is one of the probe paths.
+    pub fn matches(&self, path: &str) -> bool {
+        [
+            &self.liveness_path,
+            &self.readiness_path,
+            &self.startup_path,
+        ]
+        .iter()
+        .any(|probe| probe.as_str() == path)
+    }
+
+    /// Returns the response to send if the path is a probe path.
+    ///
+    /// The liveness probe fails once `liveness_fail_after` has passed. The readiness probe fails
+    /// until `ready_after` has passed, when the scheduled failure is less than `unready_before`
+    /// away, and while draining.
+    pub fn respond(&self, path: &str) -> Option<ProbeResponse> {
+        if path == self.liveness_path {
+            if !self.is_liveness_failing() {
+                Some(ProbeResponse::Status(200))
+            } else {
+                Some(match self.liveness_failure {
+                    LivenessFailure::Status500 => ProbeResponse::Status(500),
+                    LivenessFailure::Status503 => ProbeResponse::Status(503),
+                    LivenessFailure::Hang => ProbeResponse::Hang,
+                })
+            }
+        } else if path == self.startup_path {
+            Some(status_response(uptime() >= self.started_after))
+        } else if path == self.readiness_path {
+            let ready = uptime() >= self.ready_after
+                && !is_draining()
+                && !matches!(time_until_failure(), Some(left) if left <= self.unready_before);
+            Some(status_response(ready))
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the liveness probe has started failing.
+    fn is_liveness_failing(&self) -> bool {
+        let fail_after = match self.liveness_fail_after {
+            None => return false,
+            Some(LivenessFailAfter::Elapsed(elapsed)) => elapsed,
+            Some(LivenessFailAfter::Fraction(fraction)) => match scheduled_lifetime() {
+                Some(lifetime) => lifetime.mul_f64(fraction),
+                None => return false,
+            },
+        };
+        uptime() >= fail_after
+    }
+}
+
+/// Maps a probe result to a response.
+const fn status_response(healthy: bool) -> ProbeResponse {
+    if healthy {
+        ProbeResponse::Status(200)
+    } else {
+        ProbeResponse::Status(503)
+    }
+}
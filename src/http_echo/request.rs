@@ -0,0 +1,522 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// The maximum length of the request line and of each header line.
+const MAX_LINE_LENGTH: u64 = 16 * 1024;
+
+/// The maximum number of header lines.
+const MAX_HEADERS: usize = 256;
+
+/// The maximum size of a request body.
+pub const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// An HTTP/1.1 request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The request method, e.g. `GET`.
+    pub method: String,
+    /// The request target, i.e. the path including the query string.
+    pub target: String,
+    /// The protocol version, e.g. `HTTP/1.1`.
+    pub version: String,
+    /// The request headers in the order received.
+    pub headers: Vec<(String, String)>,
+    /// The request body.
+    pub body: Vec<u8>,
+}
+
+/// The reasons a request could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The connection was closed or broken.
+    Closed,
+    /// The request is malformed; answered with `400 Bad Request`.
+    Malformed(String),
+    /// The request body is too large; answered with `413 Content Too Large`.
+    TooLarge,
+    /// The transfer encoding is not supported; answered with `501 Not Implemented`.
+    UnsupportedEncoding(String),
+}
+
+impl RequestError {
+    /// Returns the status code to respond with, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            RequestError::Closed => None,
+            RequestError::Malformed(_) => Some(400),
+            RequestError::TooLarge => Some(413),
+            RequestError::UnsupportedEncoding(_) => Some(501),
+        }
+    }
+}
+
+impl Request {
+    /// Returns the path of the request target, without the query string.
+    pub fn path(&self) -> &str {
+        self.target
+            .split_once('?')
+            .map_or(self.target.as_str(), |(path, _)| path)
+    }
+
+    /// Returns the query string of the request target, if any.
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Returns the raw value of the first query parameter with the given name.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value of the first header with the given name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether a comma-separated header contains the given token.
+    pub fn has_header_token(&self, name: &str, token: &str) -> bool {
+        self.headers
+            .iter()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Returns whether the request is the start of the HTTP/2 connection preface.
+    pub fn is_http2_preface(&self) -> bool {
+        self.method == "PRI" && self.target == "*" && self.version == "HTTP/2.0"
+    }
+
+    /// Returns whether the connection should be closed after responding.
+    ///
+    /// HTTP/1.1 connections are persistent unless `Connection: close` is sent;
+    /// HTTP/1.0 connections are closed unless `Connection: keep-alive` is sent.
+    pub fn wants_close(&self) -> bool {
+        if self.has_header_token("Connection", "close") {
+            return true;
+        }
+        self.version == "HTTP/1.0" && !self.has_header_token("Connection", "keep-alive")
+    }
+}
+
+/// Reads the next request from the reader.
+///
+/// The body is consumed according to the `Transfer-Encoding: chunked` or `Content-Length`
+/// headers. If the client expects a `100 Continue` before sending the body, it is written
+/// to the underlying stream.
+///
+/// # Errors
+///
+/// Returns [`RequestError::Closed`] if the connection was closed, or an error
+/// describing why the request is invalid.
+pub fn read_request<S: Read + Write>(reader: &mut BufReader<S>) -> Result<Request, RequestError> {
+    let mut request_line = String::new();
+    loop {
+        request_line.clear();
+        if read_line(reader, &mut request_line)? == 0 {
+            return Err(RequestError::Closed);
+        }
+
+        // RFC2616: should ignore any empty line(s) (CRLF only) received
+        // where a Request-Line is expected.
+        if request_line != "\r\n" && request_line != "\n" {
+            break;
+        }
+    }
+
+    // Extract the method, target and version from the request line.
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let (method, target, version) = match parts[..] {
+        [method, target, version] if version.starts_with("HTTP/1.") => (method, target, version),
+        // The start of the HTTP/2 connection preface.
+        ["PRI", "*", "HTTP/2.0"] => ("PRI", "*", "HTTP/2.0"),
+        _ => {
+            return Err(RequestError::Malformed(format!(
+                "Invalid request line: {}",
+                request_line.trim_end()
+            )))
+        }
+    };
+
+    let mut headers = Vec::new();
+    let mut header_line = String::new();
+    loop {
+        header_line.clear();
+        if read_line(reader, &mut header_line)? == 0 {
+            return Err(RequestError::Closed);
+        }
+        if header_line == "\r\n" || header_line == "\n" {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(RequestError::Malformed(String::from("Too many headers")));
+        }
+        headers.push(parse_header(&header_line)?);
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        version: version.to_string(),
+        headers,
+        body: Vec::new(),
+    };
+
+    let chunked = match request.header("Transfer-Encoding") {
+        None => false,
+        Some(encoding) if encoding.trim().eq_ignore_ascii_case("chunked") => true,
+        Some(encoding) => return Err(RequestError::UnsupportedEncoding(encoding.to_string())),
+    };
+    let content_length = content_length(&request)?;
+    if chunked || content_length > 0 {
+        if request.has_header_token("Expect", "100-continue") {
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .map_err(|_| RequestError::Closed)?;
+        }
+
+        request.body = if chunked {
+            read_chunked_body(reader)?
+        } else {
+            let mut body = vec![0; content_length];
+            reader
+                .read_exact(&mut body)
+                .map_err(|_| RequestError::Closed)?;
+            body
+        };
+    }
+
+    Ok(request)
+}
+
+/// Parses a `Name: Value` header line.
+fn parse_header(line: &str) -> Result<(String, String), RequestError> {
+    match line.split_once(':') {
+        Some((name, value)) if !name.is_empty() && !name.contains(|c: char| c.is_whitespace()) => {
+            Ok((name.to_string(), value.trim().to_string()))
+        }
+        _ => Err(RequestError::Malformed(format!(
+            "Invalid header line: {}",
+            line.trim_end()
+        ))),
+    }
+}
+
+/// Determines the length of the body from the `Content-Length` headers.
+fn content_length(request: &Request) -> Result<usize, RequestError> {
+    let mut length = None;
+    for (_, value) in request
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+    {
+        let value: usize = value
+            .parse()
+            .map_err(|_| RequestError::Malformed(format!("Invalid Content-Length: {value}")))?;
+        if length.is_some_and(|length| length != value) {
+            return Err(RequestError::Malformed(String::from(
+                "Conflicting Content-Length headers",
+            )));
+        }
+        length = Some(value);
+    }
+
+    match length {
+        Some(length) if length > MAX_BODY_SIZE => Err(RequestError::TooLarge),
+        length => Ok(length.unwrap_or(0)),
+    }
+}
+
+/// Reads a body in chunked transfer encoding, discarding any trailers.
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, RequestError> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 {
+            return Err(RequestError::Closed);
+        }
+
+        // Chunk extensions are ignored.
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| RequestError::Malformed(format!("Invalid chunk size: {size}")))?;
+        if size == 0 {
+            break;
+        }
+        if size > MAX_BODY_SIZE - body.len() {
+            return Err(RequestError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(|_| RequestError::Closed)?;
+
+        line.clear();
+        read_line(reader, &mut line)?;
+        if line != "\r\n" && line != "\n" {
+            return Err(RequestError::Malformed(String::from(
+                "Missing line break after chunk",
+            )));
+        }
+    }
+
+    // Skip the trailer section.
+    loop {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 {
+            return Err(RequestError::Closed);
+        }
+        if line == "\r\n" || line == "\n" {
+            return Ok(body);
+        }
+    }
+}
+
+/// Reads a single line of limited length.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> Result<usize, RequestError> {
+    let length = reader
+        .take(MAX_LINE_LENGTH)
+        .read_line(line)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => {
+                RequestError::Malformed(String::from("Request is not valid UTF-8"))
+            }
+            _ => RequestError::Closed,
+        })?;
+    if length as u64 == MAX_LINE_LENGTH && !line.ends_with('\n') {
+        return Err(RequestError::Malformed(String::from("Line too long")));
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A connection replaying the given input, recording what is written back.
+    struct Connection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn connection(input: impl Into<Vec<u8>>) -> BufReader<Connection> {
+        BufReader::new(Connection {
+            input: Cursor::new(input.into()),
+            output: Vec::new(),
+        })
+    }
+
+    fn read_chunked(input: &str) -> Result<Vec<u8>, RequestError> {
+        read_chunked_body(&mut Cursor::new(input.as_bytes()))
+    }
+
+    #[test]
+    fn reads_consecutive_requests_with_bodies() {
+        let mut reader = connection(
+            "POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+             \r\nGET /b?c=d HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        );
+        let first = read_request(&mut reader).unwrap();
+        assert_eq!(first.method, "POST");
+        assert_eq!(first.body, b"hello");
+
+        // The empty line between the requests is skipped.
+        let second = read_request(&mut reader).unwrap();
+        assert_eq!(second.method, "GET");
+        assert_eq!(second.path(), "/b");
+        assert_eq!(second.query_param("c"), Some("d"));
+        assert_eq!(second.header("host"), Some("example.com"));
+        assert!(second.body.is_empty());
+
+        assert_eq!(read_request(&mut reader), Err(RequestError::Closed));
+    }
+
+    #[test]
+    fn accepts_repeated_equal_content_lengths() {
+        let mut reader =
+            connection("POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nhi");
+        assert_eq!(read_request(&mut reader).unwrap().body, b"hi");
+    }
+
+    #[test]
+    fn rejects_conflicting_content_lengths() {
+        let mut reader =
+            connection("POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi!");
+        let error = read_request(&mut reader).unwrap_err();
+        assert!(matches!(error, RequestError::Malformed(_)));
+        assert_eq!(error.status(), Some(400));
+    }
+
+    #[test]
+    fn rejects_invalid_content_length() {
+        let mut reader = connection("POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n");
+        assert_eq!(read_request(&mut reader).unwrap_err().status(), Some(400));
+    }
+
+    #[test]
+    fn prefers_chunked_encoding_over_content_length() {
+        let mut reader = connection(
+            "POST / HTTP/1.1\r\nContent-Length: 100\r\nTransfer-Encoding: chunked\r\n\r\n\
+             2\r\nhi\r\n0\r\n\r\nGET /next HTTP/1.1\r\n\r\n",
+        );
+        assert_eq!(read_request(&mut reader).unwrap().body, b"hi");
+        assert_eq!(read_request(&mut reader).unwrap().target, "/next");
+    }
+
+    #[test]
+    fn rejects_unsupported_transfer_encoding() {
+        let mut reader = connection("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n");
+        let error = read_request(&mut reader).unwrap_err();
+        assert!(matches!(error, RequestError::UnsupportedEncoding(_)));
+        assert_eq!(error.status(), Some(501));
+    }
+
+    #[test]
+    fn rejects_content_length_over_limit() {
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        let error = read_request(&mut connection(request)).unwrap_err();
+        assert_eq!(error, RequestError::TooLarge);
+        assert_eq!(error.status(), Some(413));
+    }
+
+    #[test]
+    fn rejects_long_lines() {
+        let long = "a".repeat(MAX_LINE_LENGTH as usize);
+        let requests = [
+            format!("GET /{long} HTTP/1.1\r\n\r\n"),
+            format!("GET / HTTP/1.1\r\nX-Long: {long}\r\n\r\n"),
+        ];
+        for request in requests {
+            let error = read_request(&mut connection(request)).unwrap_err();
+            assert_eq!(
+                error,
+                RequestError::Malformed(String::from("Line too long"))
+            );
+            assert_eq!(error.status(), Some(400));
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_headers() {
+        let headers = "X-Header: value\r\n".repeat(MAX_HEADERS + 1);
+        let request = format!("GET / HTTP/1.1\r\n{headers}\r\n");
+        let error = read_request(&mut connection(request)).unwrap_err();
+        assert_eq!(
+            error,
+            RequestError::Malformed(String::from("Too many headers"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        for request in [
+            "GET /\r\n\r\n",
+            "GET / SPDY/3\r\n\r\n",
+            "GET / HTTP/1.1\r\nNo colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nBad Name: value\r\n\r\n",
+        ] {
+            let error = read_request(&mut connection(request)).unwrap_err();
+            assert_eq!(error.status(), Some(400), "{request:?}");
+        }
+    }
+
+    #[test]
+    fn treats_truncated_requests_as_closed() {
+        for request in [
+            "GET / HTTP/1.1\r\nHost: example.com\r\n",
+            "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi",
+        ] {
+            let error = read_request(&mut connection(request)).unwrap_err();
+            assert_eq!(error, RequestError::Closed);
+            assert_eq!(error.status(), None);
+        }
+    }
+
+    #[test]
+    fn sends_continue_before_reading_body() {
+        let mut reader =
+            connection("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nhi");
+        assert_eq!(read_request(&mut reader).unwrap().body, b"hi");
+        assert_eq!(reader.get_ref().output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn honours_connection_close() {
+        let cases = [
+            ("HTTP/1.1", "", false),
+            ("HTTP/1.1", "Connection: keep-alive, close\r\n", true),
+            ("HTTP/1.0", "", true),
+            ("HTTP/1.0", "Connection: Keep-Alive\r\n", false),
+        ];
+        for (version, header, close) in cases {
+            let request = format!("GET / {version}\r\n{header}\r\n");
+            let request = read_request(&mut connection(request)).unwrap();
+            assert_eq!(request.wants_close(), close, "{version} {header:?}");
+        }
+    }
+
+    #[test]
+    fn reads_chunks_with_extensions_and_trailers() {
+        let body =
+            read_chunked("4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n");
+        assert_eq!(body, Ok(b"Wikipedia".to_vec()));
+    }
+
+    #[test]
+    fn rejects_overflowing_chunk_size() {
+        let body = read_chunked("1\r\na\r\nffffffffffffffff\r\n");
+        assert_eq!(body, Err(RequestError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_body_over_limit() {
+        let size = format!("{:x}\r\n", MAX_BODY_SIZE + 1);
+        assert_eq!(read_chunked(&size), Err(RequestError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_invalid_chunk_size() {
+        assert!(matches!(
+            read_chunked("xyz\r\n"),
+            Err(RequestError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_line_break_after_chunk() {
+        assert!(matches!(
+            read_chunked("2\r\nabc\r\n0\r\n\r\n"),
+            Err(RequestError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::prelude::*;
+use std::io::Write;
+
+/// An HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// The status code.
+    pub status: u16,
+    /// Additional headers; these take precedence over the default headers of the same name.
+    pub headers: Vec<(String, String)>,
+    /// The response body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates a response without a body.
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Serializes the status line and headers of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `close` - Whether to ask the client to close the connection.
+    pub fn head(&self, close: bool) -> String {
+        self.head_with_length(close, self.body.len())
+    }
+
+    /// Serializes the status line and headers of the response with the given `Content-Length`.
+    ///
+    /// # Arguments
+    ///
+    /// * `close` - Whether to ask the client to close the connection.
+    /// * `content_length` - The value of the `Content-Length` header.
+    pub fn head_with_length(&self, close: bool, content_length: usize) -> String {
+        self.serialize_head(self.header_fields(Some(content_length)), close)
+    }
+
+    /// Serializes the status line and headers of a response whose body is streamed.
+    ///
+    /// # Arguments
+    ///
+    /// * `close` - Whether to ask the client to close the connection.
+    /// * `chunked` - Whether the body is sent in chunked transfer encoding; otherwise, it ends
+    ///   when the connection is closed.
+    pub fn streaming_head(&self, close: bool, chunked: bool) -> String {
+        let mut fields = self.header_fields(None);
+        if chunked {
+            fields.push((String::from("Transfer-Encoding"), String::from("chunked")));
+        }
+        self.serialize_head(fields, close)
+    }
+
+    /// Serializes the status line and the given header fields.
+    fn serialize_head(&self, fields: Vec<(String, String)>, close: bool) -> String {
+        let status = self.status;
+        let reason = reason_phrase(status);
+        let mut head = format!("HTTP/1.1 {status} {reason}\r\n");
+        for (name, value) in fields {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if close {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        head
+    }
+
+    /// Returns the header fields of the response, including the default headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_length` - The value of the `Content-Length` header, if any.
+    pub fn header_fields(&self, content_length: Option<usize>) -> Vec<(String, String)> {
+        // Setting version and date from env variable and system time respectively.
+        let version = env!("CARGO_PKG_VERSION");
+        let date = Utc::now().format("%a, %d %b %Y %T GMT").to_string();
+
+        let defaults = [
+            ("Server", format!("crashie/{version}")),
+            ("Date", date),
+            ("Cache-Control", String::from("no-cache, no-store")),
+        ];
+        let mut fields: Vec<(String, String)> = defaults
+            .into_iter()
+            .filter(|(name, _)| !self.has_header(name))
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        fields.extend(
+            self.headers
+                .iter()
+                .filter(|(name, _)| !name.eq_ignore_ascii_case("Content-Length"))
+                .cloned(),
+        );
+        if let Some(content_length) = content_length {
+            fields.push((String::from("Content-Length"), content_length.to_string()));
+        }
+        fields
+    }
+
+    /// Writes the response to the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to write to.
+    /// * `close` - Whether to ask the client to close the connection.
+    pub fn write_to<W: Write>(&self, stream: &mut W, close: bool) -> Result<(), std::io::Error> {
+        stream.write_all(self.head(close).as_bytes())?;
+        stream.write_all(&self.body)
+    }
+
+    /// Returns whether the response has a header of the given name.
+    fn has_header(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .any(|(header, _)| header.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Returns the reason phrase of an HTTP status code.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        418 => "I'm a teapot",
+        422 => "Unprocessable Content",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
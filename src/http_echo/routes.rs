@@ -0,0 +1,245 @@
+use crate::delay::Delay;
+use crate::http_echo::response::Response;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A route mapping requests to a fixed response.
+///
+/// Routes are specified as `[METHOD ]PATH;field=value;...`, where the fields are
+///
+/// * `status=CODE` - the status code (default `200`),
+/// * `header=NAME: VALUE` - a response header (may be repeated),
+/// * `latency=DELAY` - a fixed or random delay before responding, e.g. `0.5` or `uniform:0.1,2`,
+/// * `file=PATH` - a file to use as the response body, or
+/// * `body=TEXT` - a literal response body; this must be the last field and may contain semicolons.
+///
+/// A path ending in `*` matches any path starting with the preceding characters.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// The method to match, or `None` to match any method.
+    method: Option<String>,
+    /// The path pattern to match.
+    path: PathPattern,
+    /// The response to send.
+    response: Response,
+    /// The delay before responding, overriding the global latency.
+    latency: Option<Delay>,
+}
+
+/// A pattern to match request paths against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathPattern {
+    /// Matches exactly the given path.
+    Exact(String),
+    /// Matches any path starting with the given prefix.
+    Prefix(String),
+}
+
+impl Route {
+    /// Returns whether the route matches the request method and path.
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        let method_matches = match &self.method {
+            Some(expected) => expected.eq_ignore_ascii_case(method),
+            None => true,
+        };
+        let path_matches = match &self.path {
+            PathPattern::Exact(expected) => path == expected,
+            PathPattern::Prefix(prefix) => path.starts_with(prefix.as_str()),
+        };
+        method_matches && path_matches
+    }
+
+    /// Returns the response to send for the route.
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    /// Returns the delay before responding, if configured for the route.
+    pub fn latency(&self) -> Option<&Delay> {
+        self.latency.as_ref()
+    }
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (target, mut fields) = match input.split_once(';') {
+            Some((target, fields)) => (target.trim(), fields),
+            None => (input.trim(), ""),
+        };
+
+        let (method, path) = match target.split_once(char::is_whitespace) {
+            Some((method, path)) if method != "*" => (Some(method.to_uppercase()), path.trim()),
+            Some((_, path)) => (None, path.trim()),
+            None => (None, target),
+        };
+        if !path.starts_with('/') {
+            return Err(format!("Route path must start with '/': {path}"));
+        }
+        let path = match path.strip_suffix('*') {
+            Some(prefix) => PathPattern::Prefix(prefix.to_string()),
+            None => PathPattern::Exact(path.to_string()),
+        };
+
+        let mut response = Response::empty(200);
+        let mut latency = None;
+        let mut has_body = false;
+        while !fields.is_empty() {
+            // A literal body consumes the remainder of the specification.
+            if let Some(body) = fields.strip_prefix("body=") {
+                response.body = body.as_bytes().to_vec();
+                has_body = true;
+                break;
+            }
+
+            let (field, rest) = fields.split_once(';').unwrap_or((fields, ""));
+            fields = rest;
+
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value in route field: {field}"))?;
+            match key.trim() {
+                "status" => {
+                    response.status = value
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|status| (100..=999).contains(status))
+                        .ok_or_else(|| format!("Invalid status code in route: {value}"))?;
+                }
+                "header" => {
+                    let (name, value) = value
+                        .split_once(':')
+                        .ok_or_else(|| format!("Expected NAME: VALUE in route header: {value}"))?;
+                    response
+                        .headers
+                        .push((name.trim().to_string(), value.trim().to_string()));
+                }
+                "latency" => {
+                    latency = Some(
+                        value
+                            .parse()
+                            .map_err(|e| format!("Invalid latency in route: {e}"))?,
+                    );
+                }
+                "file" => {
+                    response.body = fs::read(value.trim())
+                        .map_err(|e| format!("Failed to read route body from {value}: {e}"))?;
+                    has_body = true;
+                }
+                key => return Err(format!("Unknown route field: {key}")),
+            }
+        }
+
+        // Responses with these status codes end after the header section, such that a body
+        // would be read as the start of the next response.
+        let status = response.status;
+        if has_body && ((100..200).contains(&status) || status == 204 || status == 304) {
+            return Err(format!(
+                "Status code {status} does not allow a response body"
+            ));
+        }
+
+        Ok(Self {
+            method,
+            path,
+            response,
+            latency,
+        })
+    }
+}
+
+/// Loads routes from a file containing one route per line.
+///
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// # Errors
+///
+/// This function returns a description of the problem if the file cannot be read
+/// or contains an invalid route.
+pub fn load_routes(path: &Path) -> Result<Vec<Route>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read routes from {}: {e}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(number, line)| {
+            line.parse()
+                .map_err(|e| format!("{}:{}: {e}", path.display(), number + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_method_and_path() {
+        let route: Route = "get /health".parse().unwrap();
+        assert_eq!(route.method.as_deref(), Some("GET"));
+        assert_eq!(route.path, PathPattern::Exact(String::from("/health")));
+        assert!(route.matches("GET", "/health"));
+        assert!(!route.matches("POST", "/health"));
+        assert!(!route.matches("GET", "/health/live"));
+
+        let route: Route = "* /api/*".parse().unwrap();
+        assert_eq!(route.method, None);
+        assert!(route.matches("DELETE", "/api/items"));
+        assert!(!route.matches("GET", "/other"));
+    }
+
+    #[test]
+    fn parses_fields() {
+        let route: Route = "/teapot;status=418;header=X-Tea: Earl Grey;latency=0.5;body=a;b=c"
+            .parse()
+            .unwrap();
+        assert_eq!(route.response.status, 418);
+        assert_eq!(
+            route.response.headers,
+            [(String::from("X-Tea"), String::from("Earl Grey"))]
+        );
+        assert!(matches!(route.latency, Some(Delay::Fixed(value)) if value == 0.5));
+        assert_eq!(route.response.body, b"a;b=c");
+    }
+
+    #[test]
+    fn accepts_bodiless_status_without_body() {
+        for status in [101, 204, 304] {
+            let route: Route = format!("/;status={status}").parse().unwrap();
+            assert_eq!(route.response, Response::empty(status));
+        }
+    }
+
+    #[test]
+    fn defaults_to_empty_ok_response() {
+        let route: Route = "/".parse().unwrap();
+        assert_eq!(route.response, Response::empty(200));
+        assert!(route.latency.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_routes() {
+        for input in [
+            "",
+            "health",
+            "GET health",
+            "/;status=99",
+            "/;status=1000",
+            "/;status=ok",
+            "/;header=X-Tea",
+            "/;latency=-1",
+            "/;status",
+            "/;color=red",
+            "/;file=/nonexistent/route/body",
+            "/;status=204;body=content",
+            "/;status=304;body=",
+            "/;status=101;file=Cargo.toml",
+        ] {
+            assert!(input.parse::<Route>().is_err(), "{input}");
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::http_echo::json::Json;
+use crate::http_echo::request::Request;
+use crate::http_echo::response::Response;
+use crate::http_echo::HttpEchoConfig;
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use chrono::{SecondsFormat, Utc};
+use std::io::Write;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// The shortest interval between messages, such that a stream cannot busy-loop.
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The format of the messages of a streaming response.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Format {
+    /// Server-Sent Events.
+    Events,
+    /// Newline-delimited JSON in slowly sent chunks.
+    Chunks,
+}
+
+/// A streaming response, which emits a message at a fixed interval.
+///
+/// The stream ends after the requested number of messages, or when the server starts
+/// draining. It is cut off mid-stream if the process fails.
+#[derive(Debug)]
+pub struct Streaming {
+    format: Format,
+    interval: Duration,
+    /// The number of messages left to emit, or `None` if unlimited.
+    remaining: Option<u64>,
+    /// The sequence number of the next message.
+    sequence: u64,
+    /// Whether a message has been emitted yet.
+    started: bool,
+}
+
+impl Streaming {
+    /// Returns the streaming response if the request targets a streaming path that is not
+    /// claimed by a probe or a configured route.
+    ///
+    /// The `interval` (in milliseconds) and `count` query parameters override the configured
+    /// interval and the unlimited number of messages. Server-Sent Events continue after the
+    /// `Last-Event-ID` sent by reconnecting clients.
+    pub fn from_request(config: &HttpEchoConfig, request: &Request) -> Option<Self> {
+        if config.is_routed(request) {
+            return None;
+        }
+        let (format, interval) = match request.path() {
+            path if path == config.sse_path => (Format::Events, config.sse_interval),
+            path if path == config.stream_path => (Format::Chunks, config.stream_interval),
+            _ => return None,
+        };
+
+        let interval = request
+            .query_param("interval")
+            .and_then(|interval| interval.parse().ok())
+            .map_or(interval, Duration::from_millis)
+            .max(MIN_INTERVAL);
+        let last_event_id = match format {
+            Format::Events => request
+                .header("Last-Event-ID")
+                .and_then(|id| id.parse().ok()),
+            Format::Chunks => None,
+        };
+        Some(Self {
+            format,
+            interval,
+            remaining: request
+                .query_param("count")
+                .and_then(|count| count.parse().ok()),
+            sequence: last_event_id.map_or(1, |id: u64| id.saturating_add(1)),
+            started: false,
+        })
+    }
+
+    /// Returns the response head; the body is emitted by [`Streaming::next_message`].
+    pub fn response(&self) -> Response {
+        let content_type = match self.format {
+            Format::Events => "text/event-stream",
+            Format::Chunks => "application/x-ndjson",
+        };
+        let mut response = Response::empty(200);
+        response.headers = vec![
+            (String::from("Content-Type"), String::from(content_type)),
+            // Ask buffering proxies such as nginx to pass the messages through immediately.
+            (String::from("X-Accel-Buffering"), String::from("no")),
+        ];
+        response
+    }
+
+    /// Waits for the interval and returns the next message, or `None` once the stream ends.
+    pub fn next_message(&mut self) -> Option<Vec<u8>> {
+        // The first message is sent right away, such that clients see the stream start.
+        if self.started {
+            // Sleep in slices, such that draining ends the stream without waiting out the interval.
+            let mut left = self.interval;
+            while !left.is_zero() && !is_draining() {
+                let slice = left.min(POLL_INTERVAL);
+                sleep(slice);
+                left -= slice;
+            }
+        }
+        stall_if_frozen();
+        if self.remaining == Some(0) || is_draining() {
+            return None;
+        }
+        self.started = true;
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+
+        let sequence = self.sequence;
+        self.sequence += 1;
+        let data = Json::Object(vec![
+            (String::from("sequence"), Json::Number(sequence as f64)),
+            (
+                String::from("timestamp"),
+                Json::string(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+            ),
+        ]);
+        let message = match self.format {
+            Format::Events => format!("id: {sequence}\nevent: tick\ndata: {data}\n\n"),
+            Format::Chunks => format!("{data}\n"),
+        };
+        Some(message.into_bytes())
+    }
+
+    /// Writes the streaming response to an HTTP/1.x connection.
+    ///
+    /// Returns whether the connection needs to be closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to write to.
+    /// * `close` - Whether to ask the client to close the connection.
+    /// * `head_only` - Whether to omit the body, e.g. when responding to `HEAD` requests.
+    /// * `chunked` - Whether the client supports chunked transfer encoding. Otherwise, the end
+    ///   of the stream is signalled by closing the connection.
+    pub fn write_to<W: Write>(
+        mut self,
+        stream: &mut W,
+        close: bool,
+        head_only: bool,
+        chunked: bool,
+    ) -> Result<bool, std::io::Error> {
+        let close = close || !chunked;
+        stream.write_all(self.response().streaming_head(close, chunked).as_bytes())?;
+        stream.flush()?;
+        if head_only {
+            return Ok(close);
+        }
+
+        while let Some(message) = self.next_message() {
+            if chunked {
+                write!(stream, "{:x}\r\n", message.len())?;
+                stream.write_all(&message)?;
+                stream.write_all(b"\r\n")?;
+            } else {
+                stream.write_all(&message)?;
+            }
+            stream.flush()?;
+        }
+        if chunked {
+            stream.write_all(b"0\r\n\r\n")?;
+        }
+        Ok(close)
+    }
+}
@@ -0,0 +1,540 @@
+use crate::http_echo::announce::Announcement;
+use crate::http_echo::request::{Request, MAX_BODY_SIZE};
+use crate::http_echo::response::Response;
+use crate::http_echo::{HttpEchoConfig, Transport};
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use base64::prelude::*;
+use clap::ValueEnum;
+use std::io::{BufReader, Cursor, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+
+/// The GUID appended to the client's key to compute the accept key (RFC 6455, section 1.3).
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The maximum payload size of control frames.
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+// Opcodes.
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+// Close codes.
+const GOING_AWAY: u16 = 1001;
+const PROTOCOL_ERROR: u16 = 1002;
+const INVALID_PAYLOAD: u16 = 1007;
+const MESSAGE_TOO_BIG: u16 = 1009;
+
+/// How open WebSocket connections are notified before the process fails.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum WebSocketFailure {
+    /// Connections are not notified.
+    #[default]
+    None,
+    /// A close frame with the configured close code is sent on each connection.
+    Close,
+    /// Each connection is reset without a closing handshake.
+    Drop,
+}
+
+/// A WebSocket frame.
+struct Frame {
+    /// Whether this is the final fragment of a message.
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Errors while reading a frame.
+enum FrameError {
+    Io(std::io::Error),
+    /// The frame is invalid; the connection is closed with the given close code.
+    Invalid(u16),
+}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Frame {
+    /// Creates an unfragmented frame.
+    fn new(opcode: u8, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    /// Creates a close frame with the given close code.
+    fn close(code: u16) -> Self {
+        Self::new(CLOSE, code.to_be_bytes().to_vec())
+    }
+
+    /// Reads a masked frame sent by a client.
+    ///
+    /// Returns `Ok(None)` if the stream was closed before a frame started.
+    fn read_from<R: Read>(stream: &mut R, max_size: usize) -> Result<Option<Self>, FrameError> {
+        let mut header = [0; 2];
+        match stream.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => stream.read_exact(&mut header[1..])?,
+        }
+
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let masked = header[1] & 0x80 != 0;
+
+        // No extensions are negotiated, and clients must mask their frames.
+        if header[0] & 0x70 != 0 || !masked {
+            return Err(FrameError::Invalid(PROTOCOL_ERROR));
+        }
+
+        let length = match header[1] & 0x7f {
+            126 => {
+                let mut length = [0; 2];
+                stream.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0; 8];
+                stream.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => length as u64,
+        };
+
+        let is_control = opcode & 0x8 != 0;
+        if is_control && (!fin || length > MAX_CONTROL_PAYLOAD) {
+            return Err(FrameError::Invalid(PROTOCOL_ERROR));
+        }
+        if length > max_size as u64 {
+            return Err(FrameError::Invalid(MESSAGE_TOO_BIG));
+        }
+
+        let mut mask = [0; 4];
+        stream.read_exact(&mut mask)?;
+        let mut payload = vec![0; length as usize];
+        stream.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Some(Self {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+
+    /// Writes the frame unmasked, as sent by a server, ignoring the `fin` flag.
+    fn write_to<W: Write>(&self, stream: &mut W) -> Result<(), std::io::Error> {
+        let length = self.payload.len();
+        let mut bytes = Vec::with_capacity(length + 10);
+        // Messages are echoed unfragmented.
+        bytes.push(0x80 | self.opcode);
+        if length < 126 {
+            bytes.push(length as u8);
+        } else if length <= u16::MAX as usize {
+            bytes.push(126);
+            bytes.extend_from_slice(&(length as u16).to_be_bytes());
+        } else {
+            bytes.push(127);
+            bytes.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.payload);
+        stream.write_all(&bytes)?;
+        stream.flush()
+    }
+}
+
+/// Validates the opening handshake of a WebSocket connection.
+///
+/// Returns the `101 Switching Protocols` response head, or the response rejecting the request.
+pub fn handshake(request: &Request) -> Result<String, Response> {
+    let upgrade_required = || {
+        let mut response = Response::empty(426);
+        response.headers = vec![
+            (String::from("Upgrade"), String::from("websocket")),
+            (String::from("Sec-WebSocket-Version"), String::from("13")),
+        ];
+        response
+    };
+
+    if !request.has_header_token("Upgrade", "websocket")
+        || !request.has_header_token("Connection", "Upgrade")
+        || request.header("Sec-WebSocket-Version") != Some("13")
+    {
+        return Err(upgrade_required());
+    }
+
+    // The key must be a base64-encoded 16-byte value.
+    let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+    let is_valid_key = BASE64_STANDARD
+        .decode(key)
+        .is_ok_and(|nonce| nonce.len() == 16);
+    if request.method != "GET" || request.version != "HTTP/1.1" || !is_valid_key {
+        return Err(Response::empty(400));
+    }
+
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    let accept = BASE64_STANDARD.encode(sha1.digest().bytes());
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    ))
+}
+
+/// Echoes WebSocket messages on an upgraded connection.
+///
+/// # Arguments
+///
+/// * `reader` - The connection, possibly with data already buffered.
+/// * `config` - The configuration of the HTTP echo server.
+pub fn serve<T: Transport>(reader: BufReader<T>, config: &HttpEchoConfig) {
+    let buffered = reader.buffer().to_vec();
+    let stream = reader.into_inner();
+    let halves = stream
+        .socket()
+        .try_clone()
+        .and_then(|socket| Ok((socket, stream.split()?)));
+    let (socket, (input, output)) = match halves {
+        Ok(halves) => halves,
+        Err(e) => {
+            eprintln!("Failed to split WebSocket connection: {e}");
+            return;
+        }
+    };
+    let mut input = BufReader::new(Cursor::new(buffered).chain(input));
+
+    let (sender, receiver) = channel();
+    let announcement = Announcement::register();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut writer = Writer {
+                output,
+                socket,
+                config,
+                announcement,
+                closing: false,
+            };
+            if let Err(e) = writer.run(receiver) {
+                eprintln!("Failed to write WebSocket frames: {e}");
+            }
+        });
+
+        match echo_messages(&mut input, sender) {
+            // TLS peers commonly close the connection without a close_notify alert.
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {}
+            Err(e) => eprintln!("Failed to read WebSocket frames: {e}"),
+            Ok(()) => {}
+        }
+    });
+}
+
+/// Reads messages and echoes them until the connection is closed.
+fn echo_messages<R: Read>(input: &mut R, sender: Sender<Frame>) -> Result<(), std::io::Error> {
+    // The opcode and payload of a fragmented message being received.
+    let mut message: Option<(u8, Vec<u8>)> = None;
+
+    loop {
+        let frame = match Frame::read_from(input, MAX_BODY_SIZE) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(()),
+            Err(FrameError::Io(e)) => return Err(e),
+            Err(FrameError::Invalid(code)) => {
+                let _ = sender.send(Frame::close(code));
+                return Ok(());
+            }
+        };
+        stall_if_frozen();
+
+        // Control frames may be interleaved with the fragments of a message.
+        let (opcode, payload) = match (frame.opcode, message.take()) {
+            (PING, fragmented) => {
+                message = fragmented;
+                if sender.send(Frame::new(PONG, frame.payload)).is_err() {
+                    return Ok(());
+                }
+                continue;
+            }
+            (PONG, fragmented) => {
+                message = fragmented;
+                continue;
+            }
+            (CLOSE, _) => {
+                let _ = sender.send(close_reply(&frame.payload));
+                return Ok(());
+            }
+            (TEXT | BINARY, None) => (frame.opcode, frame.payload),
+            (CONTINUATION, Some((opcode, mut payload))) => {
+                payload.extend_from_slice(&frame.payload);
+                (opcode, payload)
+            }
+            _ => {
+                let _ = sender.send(Frame::close(PROTOCOL_ERROR));
+                return Ok(());
+            }
+        };
+
+        if payload.len() > MAX_BODY_SIZE {
+            let _ = sender.send(Frame::close(MESSAGE_TOO_BIG));
+            return Ok(());
+        }
+        if !frame.fin {
+            message = Some((opcode, payload));
+            continue;
+        }
+        if opcode == TEXT && std::str::from_utf8(&payload).is_err() {
+            let _ = sender.send(Frame::close(INVALID_PAYLOAD));
+            return Ok(());
+        }
+        if sender.send(Frame::new(opcode, payload)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns whether the close code may be sent on the wire (RFC 6455, section 7.4).
+pub fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+/// Returns the frame completing the closing handshake, which repeats the client's close code.
+///
+/// Invalid close codes and truncated close payloads are answered with a protocol error instead.
+fn close_reply(payload: &[u8]) -> Frame {
+    match payload {
+        [] => Frame::new(CLOSE, Vec::new()),
+        [first, second, ..] => match u16::from_be_bytes([*first, *second]) {
+            code if is_valid_close_code(code) => Frame::close(code),
+            _ => Frame::close(PROTOCOL_ERROR),
+        },
+        [_] => Frame::close(PROTOCOL_ERROR),
+    }
+}
+
+/// The writing half of a connection.
+struct Writer<'a, W: Write> {
+    output: W,
+    /// The underlying TCP connection, for resetting it.
+    socket: TcpStream,
+    config: &'a HttpEchoConfig,
+    /// The announcement of the failure to the peer.
+    announcement: Announcement,
+    /// Whether a close frame has been sent, after which no further frames may be sent.
+    closing: bool,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    /// Writes frames until the reading half has finished.
+    fn run(&mut self, receiver: Receiver<Frame>) -> Result<(), std::io::Error> {
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(frame) => self.send(frame)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            if is_draining() {
+                self.send(Frame::close(GOING_AWAY))?;
+            }
+            if self.announcement.is_due() {
+                self.announce()?;
+            }
+        }
+    }
+
+    /// Sends a frame, unless the connection is closing.
+    fn send(&mut self, frame: Frame) -> Result<(), std::io::Error> {
+        if self.closing {
+            return Ok(());
+        }
+        self.closing = frame.opcode == CLOSE;
+        frame.write_to(&mut self.output)
+    }
+
+    /// Notifies the peer of the imminent failure as configured.
+    fn announce(&mut self) -> Result<(), std::io::Error> {
+        match self.config.websocket_on_failure {
+            WebSocketFailure::None => {}
+            WebSocketFailure::Close => self.send(Frame::close(self.config.websocket_close_code))?,
+            WebSocketFailure::Drop => reset_on_close(&self.socket)?,
+        }
+        self.announcement.complete();
+        Ok(())
+    }
+}
+
+/// Makes the connection reset instead of shutting down gracefully once the process exits.
+#[cfg(unix)]
+fn reset_on_close(socket: &TcpStream) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    // SAFETY: The option value points to a valid `linger` structure of the given size.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            &linger as *const libc::linger as *const libc::c_void,
+            std::mem::size_of::<libc::linger>() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Resetting connections is not supported on this platform; the connection is shut down instead.
+#[cfg(not(unix))]
+fn reset_on_close(socket: &TcpStream) -> Result<(), std::io::Error> {
+    socket.shutdown(std::net::Shutdown::Both)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a masked client frame with the given header bytes, extended length and payload.
+    fn client_frame(first: u8, length: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut bytes = vec![first];
+        bytes.extend_from_slice(length);
+        bytes[1] |= 0x80;
+        bytes.extend_from_slice(&mask);
+        bytes.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        bytes
+    }
+
+    fn read_frame(bytes: &[u8], max_size: usize) -> Result<Option<Frame>, FrameError> {
+        Frame::read_from(&mut Cursor::new(bytes), max_size)
+    }
+
+    #[test]
+    fn reads_masked_frames() {
+        let bytes = client_frame(0x80 | TEXT, &[5], b"hello");
+        let frame = read_frame(&bytes, 5).ok().flatten().unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, TEXT);
+        assert_eq!(frame.payload, b"hello");
+        assert!(matches!(read_frame(&[], 5), Ok(None)));
+    }
+
+    #[test]
+    fn reads_extended_lengths() {
+        let payload = vec![b'x'; 300];
+        let bytes = client_frame(BINARY, &[126, 1, 44], &payload);
+        let frame = read_frame(&bytes, 300).ok().flatten().unwrap();
+        assert!(!frame.fin);
+        assert_eq!(frame.payload, payload);
+
+        let bytes = client_frame(BINARY, &[127, 0, 0, 0, 0, 0, 0, 1, 44], &payload);
+        let frame = read_frame(&bytes, 300).ok().flatten().unwrap();
+        assert_eq!(frame.payload, payload);
+    }
+
+    #[test]
+    fn rejects_unmasked_frames() {
+        let mut bytes = client_frame(0x80 | TEXT, &[5], b"hello");
+        bytes[1] &= 0x7f;
+        assert!(matches!(
+            read_frame(&bytes, 5),
+            Err(FrameError::Invalid(PROTOCOL_ERROR))
+        ));
+    }
+
+    #[test]
+    fn rejects_reserved_bits() {
+        let bytes = client_frame(0xc0 | TEXT, &[5], b"hello");
+        assert!(matches!(
+            read_frame(&bytes, 5),
+            Err(FrameError::Invalid(PROTOCOL_ERROR))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_control_frames() {
+        let bytes = client_frame(0x80 | PING, &[126, 0, 126], &[0; 126]);
+        assert!(matches!(
+            read_frame(&bytes, MAX_BODY_SIZE),
+            Err(FrameError::Invalid(PROTOCOL_ERROR))
+        ));
+
+        let bytes = client_frame(PING, &[0], &[]);
+        assert!(matches!(
+            read_frame(&bytes, MAX_BODY_SIZE),
+            Err(FrameError::Invalid(PROTOCOL_ERROR))
+        ));
+    }
+
+    #[test]
+    fn rejects_frames_over_max_size() {
+        let bytes = client_frame(0x80 | BINARY, &[127, 0xff, 0, 0, 0, 0, 0, 0, 0], &[]);
+        assert!(matches!(
+            read_frame(&bytes, MAX_BODY_SIZE),
+            Err(FrameError::Invalid(MESSAGE_TOO_BIG))
+        ));
+    }
+
+    #[test]
+    fn repeats_valid_close_codes() {
+        assert!(close_reply(&[]).payload.is_empty());
+        for code in [1000_u16, 1001, 1003, 1007, 1011, 1014, 3000, 4999] {
+            let payload = code.to_be_bytes();
+            assert_eq!(close_reply(&payload).payload, payload, "{code}");
+        }
+        assert_eq!(close_reply(b"\x03\xe8bye").payload, 1000_u16.to_be_bytes());
+    }
+
+    #[test]
+    fn rejects_invalid_close_codes() {
+        let protocol_error = PROTOCOL_ERROR.to_be_bytes();
+        assert_eq!(close_reply(&[0x03]).payload, protocol_error);
+        for code in [
+            0_u16,
+            999,
+            1004,
+            1005,
+            1006,
+            1015,
+            1016,
+            2999,
+            5000,
+            u16::MAX,
+        ] {
+            assert_eq!(
+                close_reply(&code.to_be_bytes()).payload,
+                protocol_error,
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let bytes = client_frame(0x80 | TEXT, &[5], b"hel");
+        assert!(matches!(
+            read_frame(&bytes, 5),
+            Err(FrameError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread::park;
+use std::time::{Duration, Instant};
+
+/// The time at which the process started.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// The time at which the failure is scheduled.
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Whether the process has stopped servicing requests.
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// Whether the process is shutting down gracefully.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// The interval at which listeners and connections check whether the process is draining.
+#[cfg(any(feature = "tcp-echo", feature = "udp-echo", feature = "http-echo"))]
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Determines what happens once the delay has passed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum FailureMode {
+    /// Terminate with the selected exit code or crash kind.
+    #[default]
+    Exit,
+    /// Stop servicing the echo servers while keeping the process alive.
+    Hang,
+}
+
+/// Records the start time of the process.
+pub fn start() {
+    STARTED.get_or_init(Instant::now);
+}
+
+/// Records that the failure is scheduled to happen after the specified delay.
+///
+/// Delays too long to be represented are treated as if no failure was scheduled.
+pub fn schedule_failure(delay: Duration) {
+    if let Some(deadline) = Instant::now().checked_add(delay) {
+        let _ = DEADLINE.set(deadline);
+    }
+}
+
+/// Returns the time since the process started.
+#[cfg(feature = "http-echo")]
+pub fn uptime() -> Duration {
+    STARTED.get_or_init(Instant::now).elapsed()
+}
+
+/// Returns the time left until the scheduled failure, if one is scheduled.
+#[cfg(feature = "http-echo")]
+pub fn time_until_failure() -> Option<Duration> {
+    DEADLINE
+        .get()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Returns the time from startup to the scheduled failure, if one is scheduled.
+#[cfg(feature = "http-echo")]
+pub fn scheduled_lifetime() -> Option<Duration> {
+    let started = *STARTED.get_or_init(Instant::now);
+    DEADLINE
+        .get()
+        .map(|deadline| deadline.saturating_duration_since(started))
+}
+
+/// Stops servicing the echo servers and blocks the calling thread forever.
+pub fn hang() -> ! {
+    println!("Hanging");
+    FROZEN.store(true, Ordering::SeqCst);
+    park_forever()
+}
+
+/// Blocks the calling thread forever if the process is hanging.
+///
+/// Echo servers call this before servicing a connection or request, such that
+/// clients are left waiting for a response that never arrives.
+#[cfg(any(feature = "tcp-echo", feature = "udp-echo", feature = "http-echo"))]
+pub fn stall_if_frozen() {
+    if FROZEN.load(Ordering::SeqCst) {
+        park_forever()
+    }
+}
+
+/// Starts draining: listeners close and open connections are finished.
+pub fn start_draining() {
+    DRAINING.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether the process is shutting down gracefully.
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::SeqCst)
+}
+
+/// Blocks the calling thread forever.
+pub fn park_forever() -> ! {
+    loop {
+        park();
+    }
+}
@@ -0,0 +1,87 @@
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, sleep};
+
+/// A bound socket on which connections are accepted.
+pub trait Listener: Send + 'static {
+    /// The accepted connections.
+    type Stream: Send + 'static;
+
+    /// Returns the local address, for logging.
+    fn describe(&self) -> Result<String, std::io::Error>;
+
+    /// Moves the listener into or out of non-blocking mode.
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error>;
+
+    /// Accepts a connection in blocking mode, along with the address of the peer.
+    fn accept_connection(&self) -> Result<(Self::Stream, String), std::io::Error>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn describe(&self) -> Result<String, std::io::Error> {
+        Ok(self.local_addr()?.to_string())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept_connection(&self) -> Result<(TcpStream, String), std::io::Error> {
+        let (stream, peer) = self.accept()?;
+        // Accepted sockets may inherit the non-blocking mode on some platforms.
+        stream.set_nonblocking(false)?;
+        Ok((stream, peer.to_string()))
+    }
+}
+
+/// Accepts connections on the given listener and spawns a new thread for each of them.
+///
+/// The listener is polled on a dedicated thread and closed as soon as the process
+/// starts draining, such that new connections are refused while open connections
+/// are being finished.
+///
+/// # Arguments
+///
+/// * `listener` - The bound listener.
+/// * `protocol` - The name of the protocol served, used for logging.
+/// * `handle_client` - The function to call for each accepted connection.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if the listener cannot be switched to non-blocking mode.
+pub fn accept_connections<L, F>(
+    listener: L,
+    protocol: &'static str,
+    handle_client: F,
+) -> Result<(), std::io::Error>
+where
+    L: Listener,
+    F: Fn(L::Stream) + Clone + Send + 'static,
+{
+    let addr = listener.describe()?;
+    listener.set_nonblocking(true)?;
+
+    thread::spawn(move || {
+        while !is_draining() {
+            match listener.accept_connection() {
+                Ok((stream, peer)) => {
+                    stall_if_frozen();
+                    println!("Accepting {protocol} connection from {peer}");
+
+                    let handle_client = handle_client.clone();
+                    thread::spawn(move || handle_client(stream));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(POLL_INTERVAL),
+                Err(e) => {
+                    eprintln!("Error accepting {protocol} connection: {e}");
+                }
+            }
+        }
+
+        println!("Closing {protocol} listener on {addr}");
+    });
+    Ok(())
+}
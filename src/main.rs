@@ -1,36 +1,121 @@
 //! crashie — when you need it to fail.
 
+mod crash;
+mod delay;
 #[cfg(feature = "http-echo")]
 mod http_echo;
+mod lifecycle;
+#[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+mod listener;
+mod oom;
 mod options;
+mod outcome;
+mod shutdown;
+mod state;
 #[cfg(feature = "tcp-echo")]
 mod tcp_echo;
+mod termination;
+#[cfg(all(feature = "tls", any(feature = "tcp-echo", feature = "http-echo")))]
+mod tls;
 #[cfg(feature = "udp-echo")]
 mod udp_echo;
+#[cfg(all(unix, feature = "unix-echo"))]
+mod unix_echo;
 
 use clap::Parser;
+use crash::CrashOptions;
+use delay::{Delay, DelayParameters};
 use dotenvy::dotenv;
+#[cfg(feature = "http-echo")]
+use http_echo::{Faults, HttpEchoConfig, Probes};
+use lifecycle::FailureMode;
 use options::Opts;
+use outcome::Outcome;
 use rand::prelude::*;
-use rand_distr::Normal;
-use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand_distr::WeightedIndex;
+use state::RunState;
+#[cfg(all(feature = "tls", any(feature = "tcp-echo", feature = "http-echo")))]
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
 use std::process::exit;
+#[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+use std::sync::Arc;
+#[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+use std::sync::Mutex;
 use std::thread::sleep;
 use std::time::Duration;
+#[cfg(feature = "tcp-echo")]
+use tcp_echo::TcpEchoConfig;
 
 fn main() {
     dotenv().ok();
-    let mut rng = thread_rng();
+    lifecycle::start();
     let opts: Opts = Opts::parse();
 
+    // Seed the random number generator, such that runs can be replayed. The echo servers derive
+    // separate streams from the seed, such that their traffic does not affect the selected outcome.
+    let seed = opts.seed.unwrap_or_else(|| thread_rng().gen());
+    println!("Using random seed {seed}");
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Count the runs across restarts.
+    let run_state = opts
+        .state_file
+        .as_ref()
+        .map(|path| match RunState::begin_run(path) {
+            Ok(state) => {
+                println!(
+                    "Starting run {} (state file {})",
+                    state.run + 1,
+                    path.display()
+                );
+                state
+            }
+            Err(e) => {
+                eprintln!("Failed to update state file: {e}");
+                exit(1);
+            }
+        });
+
+    // Handle SIGTERM and SIGINT.
+    if let Err(e) = shutdown::handle_shutdown_signals(
+        opts.shutdown_mode,
+        opts.drain_period,
+        opts.shutdown_exit_code,
+    ) {
+        eprintln!("Failed to register signal handlers: {e}");
+        exit(1);
+    }
+
+    // The certificate is shared by all TLS listeners, and only prepared if any are bound.
+    #[cfg(all(feature = "tls", any(feature = "tcp-echo", feature = "http-echo")))]
+    let tls_certificates: OnceCell<Arc<tls::CertificateResolver>> = OnceCell::new();
+
     // Bind TCP echo sockets.
     #[cfg(feature = "tcp-echo")]
+    let tcp_config = Arc::new(TcpEchoConfig {
+        latency: opts.tcp_echo_latency,
+        bandwidth: opts.tcp_echo_bandwidth,
+        buffer_size: opts.tcp_echo_buffer_size as usize,
+        rng: Mutex::new(StdRng::seed_from_u64(seed.wrapping_add(2))),
+    });
+    #[cfg(feature = "tcp-echo")]
     for addr in opts.tcp_echo_socks.iter().flatten() {
-        if let Err(e) = tcp_echo::tcp_echo(addr) {
+        if let Err(e) = tcp_echo::tcp_echo(addr, tcp_config.clone()) {
             eprintln!("Failed to bind to TCP socket: {e}");
             exit(1);
         }
     }
+    #[cfg(all(feature = "tcp-echo", feature = "tls"))]
+    for addr in opts.tls_echo_socks.iter().flatten() {
+        let certificates = tls_certificates.get_or_init(|| load_tls_certificates(&opts));
+        let tls_config = tls_server_config(certificates, &[]);
+        if let Err(e) = tcp_echo::tls_echo(addr, tcp_config.clone(), tls_config) {
+            eprintln!("Failed to bind to TLS socket: {e}");
+            exit(1);
+        }
+    }
 
     // Bind TDP echo sockets.
     #[cfg(feature = "udp-echo")]
@@ -41,63 +126,279 @@ fn main() {
         }
     }
 
+    // Bind Unix domain sockets.
+    #[cfg(all(unix, feature = "unix-echo"))]
+    for path in &opts.unix_echo_paths {
+        if let Err(e) = unix_echo::unix_echo(path, opts.unix_socket_mode, tcp_config.clone()) {
+            eprintln!("Failed to bind to Unix socket {path}: {e}");
+            exit(1);
+        }
+    }
+    #[cfg(all(unix, feature = "unix-echo"))]
+    for path in &opts.unixgram_echo_paths {
+        if let Err(e) = unix_echo::unixgram_echo(path, opts.unix_socket_mode) {
+            eprintln!("Failed to bind to Unix datagram socket {path}: {e}");
+            exit(1);
+        }
+    }
+
     // Bind HTTP sockets.
     #[cfg(feature = "http-echo")]
+    let mut http_routes = opts.http_echo_routes.clone();
+    #[cfg(feature = "http-echo")]
+    if let Some(path) = &opts.http_echo_routes_file {
+        match http_echo::load_routes(path) {
+            Ok(routes) => http_routes.extend(routes),
+            Err(e) => {
+                eprintln!("Failed to load HTTP routes: {e}");
+                exit(1);
+            }
+        }
+    }
+    #[cfg(feature = "http-echo")]
+    let http_config = Arc::new(HttpEchoConfig {
+        probes: Probes {
+            liveness_path: opts.http_echo_liveness_probe_path.clone(),
+            readiness_path: opts.http_echo_readiness_probe_path.clone(),
+            startup_path: opts.http_echo_startup_probe_path.clone(),
+            started_after: seconds_to_duration(opts.http_echo_started_after),
+            ready_after: seconds_to_duration(opts.http_echo_ready_after),
+            unready_before: seconds_to_duration(opts.http_echo_unready_before),
+            liveness_fail_after: opts.http_echo_liveness_fail_after,
+            liveness_failure: opts.http_echo_liveness_failure,
+        },
+        routes: http_routes,
+        echo_path: opts.http_echo_echo_path.clone(),
+        latency: opts.http_echo_latency,
+        faults: Faults {
+            error_probability: opts.http_echo_error_probability,
+            error_statuses: opts.http_echo_error_statuses.clone(),
+            retry_after: opts.http_echo_retry_after,
+            drop_probability: opts.http_echo_drop_probability,
+            truncate_probability: opts.http_echo_truncate_probability,
+            length_mismatch_probability: opts.http_echo_length_mismatch_probability,
+        },
+        http2_on_failure: opts.http2_on_failure,
+        http2_failure_error_code: opts.http2_failure_error_code,
+        websocket_path: opts.websocket_path.clone(),
+        websocket_on_failure: opts.websocket_on_failure,
+        websocket_close_code: opts.websocket_close_code,
+        sse_path: opts.sse_path.clone(),
+        sse_interval: Duration::from_millis(opts.sse_interval),
+        stream_path: opts.stream_path.clone(),
+        stream_interval: Duration::from_millis(opts.stream_interval),
+        #[cfg(feature = "grpc")]
+        grpc_not_serving_before: seconds_to_duration(opts.grpc_not_serving_before),
+        rng: Mutex::new(StdRng::seed_from_u64(seed.wrapping_add(1))),
+    });
+    #[cfg(feature = "http-echo")]
     for addr in opts.http_echo_socks.iter().flatten() {
-        if let Err(e) = http_echo::http_echo(addr, opts.http_echo_liveness_probe_path.clone()) {
+        if let Err(e) = http_echo::http_echo(addr, http_config.clone()) {
             eprintln!("Failed to bind to HTTP socket: {e}");
             exit(1);
         }
     }
+    #[cfg(all(feature = "http-echo", feature = "tls"))]
+    for addr in opts.https_echo_socks.iter().flatten() {
+        let certificates = tls_certificates.get_or_init(|| load_tls_certificates(&opts));
+        let tls_config = tls_server_config(certificates, &[b"h2", b"http/1.1"]);
+        if let Err(e) = http_echo::https_echo(addr, http_config.clone(), tls_config) {
+            eprintln!("Failed to bind to HTTPS socket: {e}");
+            exit(1);
+        }
+    }
+    #[cfg(feature = "grpc")]
+    for addr in opts.grpc_socks.iter().flatten() {
+        if let Err(e) = http_echo::grpc_echo(addr, http_config.clone()) {
+            eprintln!("Failed to bind to gRPC socket: {e}");
+            exit(1);
+        }
+    }
+
+    // Serve the echo servers until signalled.
+    if opts.no_exit || opts.sleep_delay.is_infinite() || opts.sleep_delay_grace.is_infinite() {
+        println!("Serving until signalled");
+        lifecycle::park_forever()
+    }
 
     let sleep_delay_grace = opts.sleep_delay_grace;
-    let sleep_delay_mean = opts.sleep_delay;
-    let sleep_delay_stddev = opts.sleep_delay_stddev;
-    let mut codes = collect_exit_codes(opts);
-    if codes.is_empty() {
-        codes.push(rng.gen_range(1_u8..=255))
+    let delay = match Delay::new(
+        opts.sleep_delay_distribution,
+        &DelayParameters {
+            mean: opts.sleep_delay,
+            stddev: opts.sleep_delay_stddev,
+            min: opts.sleep_delay_min,
+            max: opts.sleep_delay_max,
+            shape: opts.sleep_delay_shape,
+        },
+    ) {
+        Ok(delay) => delay,
+        Err(e) => {
+            eprintln!("Failed to initialize delay distribution: {e}");
+            exit(1);
+        }
+    };
+    let failure_mode = opts.failure_mode;
+    let termination_mode = opts.termination_mode;
+    let crash_options = CrashOptions {
+        oom_rate: opts.oom_rate,
+        oom_limit: opts.oom_limit,
+    };
+    let crash_kinds = opts.crash_kinds.clone();
+    let succeed_after = opts.succeed_after;
+    let fail_probability = opts.fail_probability;
+    let sequence_step = run_state.as_ref().and_then(|state| {
+        let index = usize::try_from(state.run).unwrap_or(usize::MAX);
+        let last = opts.exit_sequence.last().copied();
+        opts.exit_sequence.get(index).copied().or(last)
+    });
+    let mut outcomes: Vec<(Outcome, u32)> = collect_exit_codes(opts)
+        .into_iter()
+        .map(|(code, weight)| (Outcome::Exit(code), weight))
+        .chain(
+            crash_kinds
+                .into_iter()
+                .map(|(kind, weight)| (Outcome::Crash(kind), weight)),
+        )
+        .collect();
+    if outcomes.is_empty() {
+        outcomes.push((Outcome::Exit(rng.gen_range(1_u8..=255)), 1))
     }
 
-    // Select a random exit code.
-    let exit_code = codes.choose(&mut rng).copied().expect("set was empty");
+    // Select a random outcome according to its weight.
+    let weights = match WeightedIndex::new(outcomes.iter().map(|(_, weight)| *weight)) {
+        Ok(weights) => weights,
+        Err(e) => {
+            eprintln!("Failed to select an exit code: {e}");
+            exit(1);
+        }
+    };
+    let (mut outcome, _) = outcomes[weights.sample(&mut rng)];
+    let mut sleep_time = sleep_delay_grace + delay.sample(&mut rng);
+
+    // A configured sequence takes precedence over the random choices.
+    if let Some((code, sequence_delay)) = sequence_step {
+        outcome = Outcome::Exit(code);
+        if let Some(sequence_delay) = sequence_delay {
+            sleep_time = sleep_delay_grace + sequence_delay;
+        }
+    }
+
+    // Succeed once enough runs have happened, or by chance.
+    let attempts_exhausted = succeed_after
+        .zip(run_state.as_ref())
+        .is_some_and(|(succeed_after, state)| state.run >= succeed_after);
+    let spared = fail_probability.is_some_and(|probability| !rng.gen_bool(probability));
+    if attempts_exhausted || spared {
+        outcome = Outcome::Exit(0);
+    }
 
-    // Sleep for a random duration.
-    let sleep_time = sleep_delay_grace
-        + sample_random_sleep_duration(&mut rng, sleep_delay_mean, sleep_delay_stddev);
+    // Sleep for a random duration; heavy-tailed distributions may exceed the largest duration.
+    let sleep_duration = Duration::try_from_secs_f64(sleep_time).unwrap_or(Duration::MAX);
+    lifecycle::schedule_failure(sleep_duration);
     if sleep_time >= 1e-6 {
-        println!("Sleeping for {sleep_time:.2} seconds, then exiting with code {exit_code}");
-        sleep(Duration::from_secs_f64(sleep_time));
+        match failure_mode {
+            FailureMode::Exit => {
+                println!("Sleeping for {sleep_time:.2} seconds, then {outcome}")
+            }
+            FailureMode::Hang => println!("Sleeping for {sleep_time:.2} seconds, then hanging"),
+        }
+        sleep(sleep_duration);
+    }
+
+    // A graceful shutdown in progress takes precedence over the failure.
+    if lifecycle::is_draining() {
+        lifecycle::park_forever()
     }
 
-    println!("Exiting with code {exit_code}");
-    exit(exit_code as i32)
+    match failure_mode {
+        FailureMode::Exit => {
+            // Let HTTP/2 and WebSocket clients observe the failure before the connections break.
+            #[cfg(feature = "http-echo")]
+            if http_config.announces_failure() {
+                http_echo::announce_failure();
+            }
+            outcome.execute(termination_mode, &crash_options)
+        }
+        FailureMode::Hang => lifecycle::hang(),
+    }
 }
 
-fn sample_random_sleep_duration(
-    mut rng: &mut ThreadRng,
-    sleep_delay_mean: f64,
-    sleep_delay_stddev: f64,
-) -> f64 {
-    let normal = match Normal::new(sleep_delay_mean, sleep_delay_stddev) {
-        Ok(dist) => dist,
+/// Loads the TLS certificate from the configured files, or generates a self-signed one.
+#[cfg(all(feature = "tls", any(feature = "tcp-echo", feature = "http-echo")))]
+fn load_tls_certificates(opts: &Opts) -> Arc<tls::CertificateResolver> {
+    let certificates = match (&opts.tls_cert, &opts.tls_key) {
+        (Some(cert_path), Some(key_path)) => {
+            tls::CertificateResolver::from_files(cert_path, key_path)
+        }
+        _ => {
+            println!(
+                "Generating self-signed TLS certificate for {}",
+                opts.tls_self_signed_names.join(", ")
+            );
+            tls::CertificateResolver::self_signed(&opts.tls_self_signed_names)
+        }
+    };
+    match certificates {
+        Ok(certificates) => Arc::new(certificates),
         Err(e) => {
-            eprintln!("Failed to initialize normal distribution: {e}");
+            eprintln!("Failed to prepare TLS certificate: {e}");
             exit(1);
         }
-    };
+    }
+}
 
-    normal.sample(&mut rng).abs()
+/// Creates the configuration of a TLS listener offering the given application protocols.
+#[cfg(all(feature = "tls", any(feature = "tcp-echo", feature = "http-echo")))]
+fn tls_server_config(
+    certificates: &Arc<tls::CertificateResolver>,
+    alpn_protocols: &[&[u8]],
+) -> Arc<rustls::ServerConfig> {
+    match tls::server_config(certificates.clone(), alpn_protocols) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to configure TLS: {e}");
+            exit(1);
+        }
+    }
+}
+
+/// Converts seconds to a duration, saturating at the maximum duration for infinite values.
+#[cfg(feature = "http-echo")]
+fn seconds_to_duration(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
 }
 
-fn collect_exit_codes(opts: Opts) -> Vec<u8> {
-    let mut codes: HashSet<u8> = HashSet::from_iter(opts.exit_codes.iter().copied());
+fn collect_exit_codes(opts: Opts) -> Vec<(u8, u32)> {
+    let mut codes = WeightedCodes::new();
+    for (code, weight) in opts.exit_codes.iter().copied() {
+        codes.insert_weighted(code, weight);
+    }
     add_signals(opts, &mut codes);
-    codes.into_iter().collect()
+    codes.0.into_iter().collect()
+}
+
+/// Exit codes and their weights; codes provided multiple times retain their highest weight.
+struct WeightedCodes(BTreeMap<u8, u32>);
+
+impl WeightedCodes {
+    fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    fn insert(&mut self, code: u8) {
+        self.insert_weighted(code, 1);
+    }
+
+    fn insert_weighted(&mut self, code: u8, weight: u32) {
+        let entry = self.0.entry(code).or_insert(weight);
+        *entry = (*entry).max(weight);
+    }
 }
 
-fn add_signals(opts: Opts, codes: &mut HashSet<u8>) {
-    for signal in opts.signal {
-        codes.insert(signal_to_exit(signal));
+fn add_signals(opts: Opts, codes: &mut WeightedCodes) {
+    for (signal, weight) in opts.signal {
+        codes.insert_weighted(signal_to_exit(signal), weight);
     }
     if opts.sighup {
         codes.insert(signal_to_exit(1));
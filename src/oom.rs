@@ -0,0 +1,55 @@
+use crate::lifecycle::park_forever;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// The size of a single allocation.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Allocates and touches memory at the specified rate until the process is killed.
+///
+/// Memory is allocated in chunks of one MiB, each of which is written to in order to
+/// force the pages to be backed by physical memory. If a limit is provided, the allocated
+/// memory is held once the limit is reached and the process stays alive.
+///
+/// # Arguments
+///
+/// * `rate` - The allocation rate, in MiB per second.
+/// * `limit` - The optional maximum amount of memory to allocate, in MiB.
+pub fn grow_memory(rate: f64, limit: Option<u64>) -> ! {
+    let start = Instant::now();
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+    let mut allocated: u64 = 0;
+    let mut last_report = 0;
+
+    loop {
+        let mut target = (start.elapsed().as_secs_f64() * rate) as u64;
+        if let Some(limit) = limit {
+            target = target.min(limit);
+        }
+
+        while allocated < target {
+            let mut chunk = Vec::new();
+            if let Err(e) = chunk.try_reserve_exact(CHUNK_SIZE) {
+                eprintln!("Failed to allocate memory after {allocated} MiB: {e}");
+                std::process::abort();
+            }
+
+            // Touch every byte so that the pages are actually committed.
+            chunk.resize(CHUNK_SIZE, 0xAA);
+            chunks.push(chunk);
+            allocated += 1;
+        }
+
+        if allocated / 100 > last_report {
+            last_report = allocated / 100;
+            println!("Allocated {allocated} MiB");
+        }
+
+        if limit.is_some_and(|limit| allocated >= limit) {
+            println!("Reached the memory limit of {allocated} MiB, holding memory");
+            park_forever();
+        }
+
+        sleep(Duration::from_millis(10));
+    }
+}
@@ -1,21 +1,52 @@
-use clap::Parser;
+use crate::crash::CrashKind;
+#[cfg(any(feature = "tcp-echo", feature = "http-echo"))]
+use crate::delay::Delay;
+use crate::delay::DelayDistribution;
+#[cfg(feature = "http-echo")]
+use crate::http_echo::{
+    is_valid_close_code, Http2Failure, LivenessFailAfter, LivenessFailure, Route, WebSocketFailure,
+};
+use crate::lifecycle::FailureMode;
+use crate::shutdown::ShutdownMode;
+use crate::termination::TerminationMode;
+#[cfg(all(unix, feature = "unix-echo"))]
+use crate::unix_echo::UnixSocketPath;
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::{Parser, ValueEnum};
+use std::ffi::OsStr;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 
 const HELP_SECTION_CRASH_AFTER: &str = "Delay (crash after)";
+const HELP_SECTION_CRASHES: &str = "Crashes";
 const HELP_SECTION_ECHO_SERVER: &str = "Echo Server";
 const HELP_SECTION_ECHO_SERVER_HTTP: &str = "Echo Server (HTTP)";
+#[cfg(feature = "grpc")]
+const HELP_SECTION_ECHO_SERVER_GRPC: &str = "Echo Server (gRPC)";
 const HELP_SECTION_EXIT_CODES: &str = "Exit Codes";
 const HELP_SECTION_EXIT_CODES_POSIX: &str = "Exit Codes (POSIX)";
 const HELP_SECTION_EXIT_CODES_NON_POSIX: &str = "Exit Codes (non-POSIX)";
+const HELP_SECTION_SHUTDOWN: &str = "Shutdown (SIGTERM, SIGINT)";
+const HELP_SECTION_RESTARTS: &str = "Restarts";
+#[cfg(feature = "tls")]
+const HELP_SECTION_TLS: &str = "TLS";
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Opts {
+    #[clap(
+        long = "seed",
+        help = "The seed for all random choices; randomly selected if not specified",
+        value_name = "SEED",
+        env = "CRASHIE_SEED"
+    )]
+    pub seed: Option<u64>,
+
     #[clap(
         short = 'd',
         long = "delay",
         help_heading = HELP_SECTION_CRASH_AFTER,
-        help = "The sleep duration before exiting, in seconds",
+        help = "The (mean) sleep duration before exiting, in seconds; the scale of the Weibull distribution",
         value_name = "SECONDS",
         allow_negative_numbers = false,
         default_value = "9.0",
@@ -34,6 +65,47 @@ pub struct Opts {
         env = "CRASHIE_SLEEP_DELAY_STDDEV"
     )]
     pub sleep_delay_stddev: f64,
+    #[clap(
+        long = "delay-distribution",
+        help_heading = HELP_SECTION_CRASH_AFTER,
+        help = "The distribution to sample the sleep duration from",
+        value_name = "DISTRIBUTION",
+        value_enum,
+        default_value = "normal",
+        env = "CRASHIE_SLEEP_DELAY_DISTRIBUTION"
+    )]
+    pub sleep_delay_distribution: DelayDistribution,
+    #[clap(
+        long = "delay-min",
+        help_heading = HELP_SECTION_CRASH_AFTER,
+        help = "The minimum sleep duration of the uniform and truncated normal distributions, in seconds",
+        value_name = "SECONDS",
+        allow_negative_numbers = false,
+        default_value = "0.0",
+        value_parser(parse_seconds),
+        env = "CRASHIE_SLEEP_DELAY_MIN"
+    )]
+    pub sleep_delay_min: f64,
+    #[clap(
+        long = "delay-max",
+        help_heading = HELP_SECTION_CRASH_AFTER,
+        help = "The maximum sleep duration of the uniform and truncated normal distributions, in seconds",
+        value_name = "SECONDS",
+        allow_negative_numbers = false,
+        value_parser(parse_seconds),
+        env = "CRASHIE_SLEEP_DELAY_MAX"
+    )]
+    pub sleep_delay_max: Option<f64>,
+    #[clap(
+        long = "delay-shape",
+        help_heading = HELP_SECTION_CRASH_AFTER,
+        help = "The shape parameter of the Weibull distribution",
+        value_name = "SHAPE",
+        default_value = "1.0",
+        value_parser(parse_rate),
+        env = "CRASHIE_SLEEP_DELAY_SHAPE"
+    )]
+    pub sleep_delay_shape: f64,
     #[clap(
         long = "delay-grace-period",
         help_heading = HELP_SECTION_CRASH_AFTER,
@@ -45,6 +117,85 @@ pub struct Opts {
         env = "CRASHIE_SLEEP_DELAY_GRACE_PERIOD"
     )]
     pub sleep_delay_grace: f64,
+    #[clap(
+        long = "no-exit",
+        help_heading = HELP_SECTION_CRASH_AFTER,
+        help = "Never exit; only serve the echo servers until signalled (same as --delay=inf)",
+        env = "CRASHIE_NO_EXIT"
+    )]
+    pub no_exit: bool,
+
+    #[clap(
+        long = "state-file",
+        help_heading = HELP_SECTION_RESTARTS,
+        help = "A file in which to count the runs across restarts",
+        value_name = "PATH",
+        env = "CRASHIE_STATE_FILE"
+    )]
+    pub state_file: Option<PathBuf>,
+    #[clap(
+        long = "exit-sequence",
+        help_heading = HELP_SECTION_RESTARTS,
+        help = "The exit code (and optional delay) of each successive run; the last entry repeats",
+        value_name = "CODE[@SECONDS]",
+        use_value_delimiter(true),
+        value_parser(parse_sequence_step),
+        requires = "state_file",
+        env = "CRASHIE_EXIT_SEQUENCE"
+    )]
+    pub exit_sequence: Vec<(u8, Option<f64>)>,
+    #[clap(
+        long = "succeed-after",
+        help_heading = HELP_SECTION_RESTARTS,
+        help = "Exit with code 0 once the specified number of runs have happened",
+        value_name = "RUNS",
+        requires = "state_file",
+        conflicts_with = "exit_sequence",
+        env = "CRASHIE_SUCCEED_AFTER"
+    )]
+    pub succeed_after: Option<u64>,
+    #[clap(
+        long = "fail-probability",
+        help_heading = HELP_SECTION_RESTARTS,
+        help = "The probability of a run failing; otherwise it exits with code 0",
+        value_name = "PROBABILITY",
+        value_parser(parse_probability),
+        conflicts_with = "exit_sequence",
+        env = "CRASHIE_FAIL_PROBABILITY"
+    )]
+    pub fail_probability: Option<f64>,
+
+    #[clap(
+        long = "shutdown",
+        help_heading = HELP_SECTION_SHUTDOWN,
+        help = "How to react to SIGTERM and SIGINT",
+        value_name = "MODE",
+        value_enum,
+        default_value = "immediate",
+        env = "CRASHIE_SHUTDOWN"
+    )]
+    pub shutdown_mode: ShutdownMode,
+    #[clap(
+        long = "drain-period",
+        help_heading = HELP_SECTION_SHUTDOWN,
+        help = "The duration to finish open connections for when draining, in seconds",
+        value_name = "SECONDS",
+        allow_negative_numbers = false,
+        default_value = "5.0",
+        value_parser(parse_drain_period),
+        env = "CRASHIE_DRAIN_PERIOD"
+    )]
+    pub drain_period: f64,
+    #[clap(
+        long = "shutdown-exit-code",
+        help_heading = HELP_SECTION_SHUTDOWN,
+        help = "The exit code to use after draining",
+        value_name = "CODE",
+        allow_negative_numbers = false,
+        default_value = "0",
+        env = "CRASHIE_SHUTDOWN_EXIT_CODE"
+    )]
+    pub shutdown_exit_code: u8,
 
     #[cfg_attr(
         feature = "tcp-echo",
@@ -60,6 +211,36 @@ pub struct Opts {
     )]
     #[cfg_attr(not(feature = "tcp-echo"), clap(skip))]
     pub tcp_echo_socks: Vec<Vec<SocketAddr>>,
+    #[cfg(feature = "tcp-echo")]
+    #[clap(
+        long = "tcp-latency",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "Delay each echoed chunk of stream data, e.g. \"0.05\" or \"normal:0.05,0.01\" for jitter",
+        value_name = "DELAY",
+        env = "CRASHIE_TCP_LATENCY"
+    )]
+    pub tcp_echo_latency: Option<Delay>,
+    #[cfg(feature = "tcp-echo")]
+    #[clap(
+        long = "tcp-bandwidth",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "Limit the rate at which stream data is echoed per connection, in bytes per second",
+        value_name = "BYTES_PER_SECOND",
+        value_parser(parse_rate),
+        env = "CRASHIE_TCP_BANDWIDTH"
+    )]
+    pub tcp_echo_bandwidth: Option<f64>,
+    #[cfg(feature = "tcp-echo")]
+    #[clap(
+        long = "tcp-buffer-size",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "The maximum number of bytes of stream data read and echoed at once",
+        value_name = "BYTES",
+        default_value = "512",
+        value_parser = clap::value_parser!(u32).range(1..=16 * 1024 * 1024),
+        env = "CRASHIE_TCP_BUFFER_SIZE"
+    )]
+    pub tcp_echo_buffer_size: u32,
     #[cfg_attr(
         feature = "udp-echo",
         clap(
@@ -74,6 +255,89 @@ pub struct Opts {
     )]
     #[cfg_attr(not(feature = "udp-echo"), clap(skip))]
     pub udp_echo_socks: Vec<Vec<SocketAddr>>,
+    #[cfg(all(unix, feature = "unix-echo"))]
+    #[clap(
+        long = "bind-unix-echo",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "Provide echo on the specified Unix domain stream sockets; prefix abstract names with @",
+        value_name = "PATH",
+        use_value_delimiter(true),
+        env = "CRASHIE_BIND_UNIX_ECHO"
+    )]
+    pub unix_echo_paths: Vec<UnixSocketPath>,
+    #[cfg(all(unix, feature = "unix-echo"))]
+    #[clap(
+        long = "bind-unixgram-echo",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "Provide echo on the specified Unix domain datagram sockets; prefix abstract names with @",
+        value_name = "PATH",
+        use_value_delimiter(true),
+        env = "CRASHIE_BIND_UNIXGRAM_ECHO"
+    )]
+    pub unixgram_echo_paths: Vec<UnixSocketPath>,
+    #[cfg(all(unix, feature = "unix-echo"))]
+    #[clap(
+        long = "unix-socket-mode",
+        help_heading = HELP_SECTION_ECHO_SERVER,
+        help = "The octal permissions of the Unix domain socket files, e.g. 660",
+        value_name = "MODE",
+        value_parser(parse_file_mode),
+        env = "CRASHIE_UNIX_SOCKET_MODE"
+    )]
+    pub unix_socket_mode: Option<u32>,
+    #[cfg(all(feature = "tcp-echo", feature = "tls"))]
+    #[clap(
+        long = "bind-tls-echo",
+        help_heading = HELP_SECTION_TLS,
+        help = "Provide TCP echo over TLS on the specified addresses",
+        value_name = "SOCK_ADDR",
+        use_value_delimiter(true),
+        value_parser(parse_socket_addr),
+        env = "CRASHIE_BIND_TLS_ECHO"
+    )]
+    pub tls_echo_socks: Vec<Vec<SocketAddr>>,
+    #[cfg(all(feature = "http-echo", feature = "tls"))]
+    #[clap(
+        long = "bind-https-echo",
+        help_heading = HELP_SECTION_TLS,
+        help = "Provide HTTP echo over TLS on the specified addresses",
+        value_name = "SOCK_ADDR",
+        use_value_delimiter(true),
+        value_parser(parse_socket_addr),
+        env = "CRASHIE_BIND_HTTPS_ECHO"
+    )]
+    pub https_echo_socks: Vec<Vec<SocketAddr>>,
+    #[cfg(feature = "tls")]
+    #[clap(
+        long = "tls-cert",
+        help_heading = HELP_SECTION_TLS,
+        help = "The PEM file containing the certificate chain; reloaded when changed",
+        value_name = "PATH",
+        requires = "tls_key",
+        env = "CRASHIE_TLS_CERT"
+    )]
+    pub tls_cert: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    #[clap(
+        long = "tls-key",
+        help_heading = HELP_SECTION_TLS,
+        help = "The PEM file containing the private key; reloaded when changed",
+        value_name = "PATH",
+        requires = "tls_cert",
+        env = "CRASHIE_TLS_KEY"
+    )]
+    pub tls_key: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    #[clap(
+        long = "tls-self-signed-name",
+        help_heading = HELP_SECTION_TLS,
+        help = "The host names of the self-signed certificate generated if no certificate is provided",
+        value_name = "NAME",
+        use_value_delimiter(true),
+        default_value = "localhost",
+        env = "CRASHIE_TLS_SELF_SIGNED_NAMES"
+    )]
+    pub tls_self_signed_names: Vec<String>,
     #[cfg_attr(
         feature = "http-echo",
         clap(
@@ -102,6 +366,319 @@ pub struct Opts {
     )]
     #[cfg_attr(not(feature = "http-echo"), clap(skip))]
     pub http_echo_liveness_probe_path: String,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "liveness-fail-after",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "Fail the liveness probe after the given seconds, or percentage of the delay (e.g. 80%)",
+        value_name = "SECONDS|PERCENT",
+        value_parser(parse_liveness_fail_after),
+        env = "CRASHIE_LIVENESS_FAIL_AFTER"
+    )]
+    pub http_echo_liveness_fail_after: Option<LivenessFailAfter>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "liveness-failure",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "How the liveness probe fails",
+        value_name = "MODE",
+        value_enum,
+        default_value = "503",
+        env = "CRASHIE_LIVENESS_FAILURE"
+    )]
+    pub http_echo_liveness_failure: LivenessFailure,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-route",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "Respond to matching requests, e.g. \"GET /api/*;status=200;header=Content-Type: text/plain;body=OK\"",
+        value_name = "ROUTE",
+        env = "CRASHIE_HTTP_ROUTE"
+    )]
+    pub http_echo_routes: Vec<Route>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-routes-file",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "A file containing one HTTP route per line",
+        value_name = "PATH",
+        env = "CRASHIE_HTTP_ROUTES_FILE"
+    )]
+    pub http_echo_routes_file: Option<PathBuf>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-latency",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "Delay HTTP responses other than probes, e.g. \"0.5\" or \"uniform:0.1,2\"",
+        value_name = "DELAY",
+        env = "CRASHIE_HTTP_LATENCY"
+    )]
+    pub http_echo_latency: Option<Delay>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-error-probability",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The probability of responding with an error status",
+        value_name = "PROBABILITY",
+        default_value = "0.0",
+        value_parser(parse_probability),
+        env = "CRASHIE_HTTP_ERROR_PROBABILITY"
+    )]
+    pub http_echo_error_probability: f64,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-error-status",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The status codes to pick injected errors from",
+        value_name = "CODE",
+        use_value_delimiter(true),
+        default_value = "500,502,503,504",
+        value_parser(clap::value_parser!(u16).range(100..=999)),
+        env = "CRASHIE_HTTP_ERROR_STATUS"
+    )]
+    pub http_echo_error_statuses: Vec<u16>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-retry-after",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "Send a Retry-After header with injected errors, in seconds",
+        value_name = "SECONDS",
+        env = "CRASHIE_HTTP_RETRY_AFTER"
+    )]
+    pub http_echo_retry_after: Option<u64>,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-drop-probability",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The probability of closing the connection without responding",
+        value_name = "PROBABILITY",
+        default_value = "0.0",
+        value_parser(parse_probability),
+        env = "CRASHIE_HTTP_DROP_PROBABILITY"
+    )]
+    pub http_echo_drop_probability: f64,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-truncate-probability",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The probability of sending a partial response before closing the connection",
+        value_name = "PROBABILITY",
+        default_value = "0.0",
+        value_parser(parse_probability),
+        env = "CRASHIE_HTTP_TRUNCATE_PROBABILITY"
+    )]
+    pub http_echo_truncate_probability: f64,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http-length-mismatch-probability",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The probability of sending a Content-Length that does not match the body",
+        value_name = "PROBABILITY",
+        default_value = "0.0",
+        value_parser(parse_probability),
+        env = "CRASHIE_HTTP_LENGTH_MISMATCH_PROBABILITY"
+    )]
+    pub http_echo_length_mismatch_probability: f64,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http2-on-failure",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "How open HTTP/2 connections are notified before the process exits",
+        value_name = "MODE",
+        value_enum,
+        default_value = "none",
+        env = "CRASHIE_HTTP2_ON_FAILURE"
+    )]
+    pub http2_on_failure: Http2Failure,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "http2-failure-error-code",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The HTTP/2 error code of the GOAWAY or RST_STREAM frames sent before the process exits",
+        value_name = "CODE",
+        default_value = "0",
+        env = "CRASHIE_HTTP2_FAILURE_ERROR_CODE"
+    )]
+    pub http2_failure_error_code: u32,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "websocket-path",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The request path on which WebSocket connections are accepted and messages are echoed",
+        value_name = "PATH",
+        default_value = "/ws",
+        env = "CRASHIE_WEBSOCKET_PATH"
+    )]
+    pub websocket_path: String,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "websocket-on-failure",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "How open WebSocket connections are notified before the process exits",
+        value_name = "MODE",
+        value_enum,
+        default_value = "none",
+        env = "CRASHIE_WEBSOCKET_ON_FAILURE"
+    )]
+    pub websocket_on_failure: WebSocketFailure,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "websocket-close-code",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The close code sent to WebSocket clients before the process exits",
+        value_name = "CODE",
+        default_value = "1001",
+        value_parser(parse_close_code),
+        env = "CRASHIE_WEBSOCKET_CLOSE_CODE"
+    )]
+    pub websocket_close_code: u16,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "sse-path",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The request path on which Server-Sent Events are streamed",
+        value_name = "PATH",
+        default_value = "/sse",
+        env = "CRASHIE_SSE_PATH"
+    )]
+    pub sse_path: String,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "sse-interval",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The interval between Server-Sent Events, in milliseconds; overridden by the interval query parameter",
+        value_name = "MILLISECONDS",
+        default_value = "1000",
+        env = "CRASHIE_SSE_INTERVAL"
+    )]
+    pub sse_interval: u64,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "stream-path",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The request path on which a slow chunked response is streamed",
+        value_name = "PATH",
+        default_value = "/stream",
+        env = "CRASHIE_STREAM_PATH"
+    )]
+    pub stream_path: String,
+    #[cfg(feature = "http-echo")]
+    #[clap(
+        long = "stream-interval",
+        help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+        help = "The interval between chunks of the streamed response, in milliseconds; overridden by the interval query parameter",
+        value_name = "MILLISECONDS",
+        default_value = "1000",
+        env = "CRASHIE_STREAM_INTERVAL"
+    )]
+    pub stream_interval: u64,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-readiness-probe-path",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The request path on which to serve readiness probe results",
+            value_name = "HTTP_PATH",
+            default_value = "/health/ready",
+            env = "CRASHIE_HTTP_READINESS_PROBE_PATH"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_readiness_probe_path: String,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-startup-probe-path",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The request path on which to serve startup probe results",
+            value_name = "HTTP_PATH",
+            default_value = "/health/startup",
+            env = "CRASHIE_HTTP_STARTUP_PROBE_PATH"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_startup_probe_path: String,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-echo-path",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The request path below which requests are reflected back as JSON",
+            value_name = "HTTP_PATH",
+            default_value = "/anything",
+            env = "CRASHIE_HTTP_ECHO_PATH"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_echo_path: String,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-started-after",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The duration after startup during which the startup probe fails, in seconds",
+            value_name = "SECONDS",
+            allow_negative_numbers = false,
+            default_value = "0.0",
+            value_parser(parse_seconds),
+            env = "CRASHIE_HTTP_STARTED_AFTER"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_started_after: f64,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-ready-after",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The duration after startup during which the readiness probe fails, in seconds",
+            value_name = "SECONDS",
+            allow_negative_numbers = false,
+            default_value = "0.0",
+            value_parser(parse_seconds),
+            env = "CRASHIE_HTTP_READY_AFTER"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_ready_after: f64,
+    #[cfg_attr(
+        feature = "http-echo",
+        clap(
+            long = "http-unready-before",
+            help_heading = HELP_SECTION_ECHO_SERVER_HTTP,
+            help = "The duration before the failure during which the readiness probe fails, in seconds",
+            value_name = "SECONDS",
+            allow_negative_numbers = false,
+            default_value = "0.0",
+            value_parser(parse_seconds),
+            env = "CRASHIE_HTTP_UNREADY_BEFORE"
+        )
+    )]
+    #[cfg_attr(not(feature = "http-echo"), clap(skip))]
+    pub http_echo_unready_before: f64,
+    #[cfg(feature = "grpc")]
+    #[clap(
+        long = "bind-grpc",
+        help_heading = HELP_SECTION_ECHO_SERVER_GRPC,
+        help = "Provide the gRPC health, reflection and echo services on the specified addresses",
+        value_name = "SOCK_ADDR",
+        use_value_delimiter(true),
+        value_parser(parse_socket_addr),
+        env = "CRASHIE_BIND_GRPC"
+    )]
+    pub grpc_socks: Vec<Vec<SocketAddr>>,
+    #[cfg(feature = "grpc")]
+    #[clap(
+        long = "grpc-not-serving-before",
+        help_heading = HELP_SECTION_ECHO_SERVER_GRPC,
+        help = "The duration before the failure during which gRPC health checks report NOT_SERVING, in seconds",
+        value_name = "SECONDS",
+        allow_negative_numbers = false,
+        default_value = "5.0",
+        value_parser(parse_seconds),
+        env = "CRASHIE_GRPC_NOT_SERVING_BEFORE"
+    )]
+    pub grpc_not_serving_before: f64,
 
     #[clap(
         short = 'e',
@@ -109,22 +686,73 @@ pub struct Opts {
         help_heading = HELP_SECTION_EXIT_CODES,
         use_value_delimiter(true),
         allow_negative_numbers = false,
-        help = "Exit with the specified code(s)",
+        value_parser(parse_weighted_exit_code),
+        value_name = "CODE[:WEIGHT]",
+        help = "Exit with the specified code(s), optionally weighted",
         env = "CRASHIE_EXIT_CODES"
     )]
-    pub exit_codes: Vec<u8>,
+    pub exit_codes: Vec<(u8, u32)>,
     #[clap(
         short = 's',
         long = "signals",
         help_heading = HELP_SECTION_EXIT_CODES,
         use_value_delimiter(true),
-        value_parser(parse_signal),
-        value_name = "NUMBER",
+        value_parser(parse_weighted_signal),
+        value_name = "NUMBER[:WEIGHT]",
         allow_negative_numbers = false,
-        help = "Arbitrary signal (exit code 128+SIGNAL)",
+        help = "Arbitrary signal (exit code 128+SIGNAL), optionally weighted, e.g. 9:5 for a weighted --sigkill",
         env = "CRASHIE_SIGNALS"
     )]
-    pub signal: Vec<u8>,
+    pub signal: Vec<(u8, u32)>,
+    #[clap(
+        long = "termination-mode",
+        help_heading = HELP_SECTION_EXIT_CODES,
+        help = "Whether to exit with 128+SIGNAL or to actually raise the signal",
+        value_name = "MODE",
+        value_enum,
+        default_value = "exit",
+        env = "CRASHIE_TERMINATION_MODE"
+    )]
+    pub termination_mode: TerminationMode,
+
+    #[clap(
+        long = "crash-kind",
+        help_heading = HELP_SECTION_CRASHES,
+        help = "Crash with the specified kind(s) of genuine failure, optionally weighted",
+        value_name = "KIND[:WEIGHT]",
+        value_parser(WeightedCrashKindParser),
+        use_value_delimiter(true),
+        env = "CRASHIE_CRASH_KINDS"
+    )]
+    pub crash_kinds: Vec<(CrashKind, u32)>,
+    #[clap(
+        long = "failure",
+        help_heading = HELP_SECTION_CRASHES,
+        help = "Whether to terminate or to hang once the delay has passed",
+        value_name = "MODE",
+        value_enum,
+        default_value = "exit",
+        env = "CRASHIE_FAILURE"
+    )]
+    pub failure_mode: FailureMode,
+    #[clap(
+        long = "oom-rate",
+        help_heading = HELP_SECTION_CRASHES,
+        help = "The rate at which to allocate memory when running out of memory, in MiB per second",
+        value_name = "MIB_PER_SECOND",
+        default_value = "100",
+        value_parser(parse_rate),
+        env = "CRASHIE_OOM_RATE"
+    )]
+    pub oom_rate: f64,
+    #[clap(
+        long = "oom-limit",
+        help_heading = HELP_SECTION_CRASHES,
+        help = "The maximum amount of memory to allocate when running out of memory, in MiB",
+        value_name = "MIB",
+        env = "CRASHIE_OOM_LIMIT"
+    )]
+    pub oom_limit: Option<u64>,
 
     #[cfg_attr(
         feature = "posix",
@@ -434,16 +1062,150 @@ fn parse_signal(input: &str) -> Result<u8, String> {
     }
 }
 
+fn parse_weighted_signal(input: &str) -> Result<(u8, u32), String> {
+    let (signal, weight) = split_weight(input)?;
+    Ok((parse_signal(signal)?, weight))
+}
+
+fn parse_weighted_exit_code(input: &str) -> Result<(u8, u32), String> {
+    let (code, weight) = split_weight(input)?;
+    let code: u8 = code.parse().map_err(|e| format!("{e}"))?;
+    Ok((code, weight))
+}
+
+fn parse_weighted_crash_kind(input: &str) -> Result<(CrashKind, u32), String> {
+    let (kind, weight) = split_weight(input)?;
+    Ok((CrashKind::from_str(kind, true)?, weight))
+}
+
+/// Parses weighted crash kinds, listing the kinds in the help like a plain enum value.
+#[derive(Clone)]
+struct WeightedCrashKindParser;
+
+impl TypedValueParser for WeightedCrashKindParser {
+    type Value = (CrashKind, u32);
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        parse_weighted_crash_kind.parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        let kinds = CrashKind::value_variants().iter();
+        Some(Box::new(kinds.filter_map(ValueEnum::to_possible_value)))
+    }
+}
+
+fn parse_sequence_step(input: &str) -> Result<(u8, Option<f64>), String> {
+    match input.split_once('@') {
+        Some((code, delay)) => {
+            let code: u8 = code.parse().map_err(|e| format!("{e}"))?;
+            let delay = parse_seconds(delay)?;
+            if delay.is_infinite() {
+                return Err(String::from("The delay of a sequence step must be finite"));
+            }
+            Ok((code, Some(delay)))
+        }
+        None => Ok((input.parse().map_err(|e| format!("{e}"))?, None)),
+    }
+}
+
+/// Splits an optional `:WEIGHT` suffix off the input; the weight defaults to 1.
+///
+/// Weights must be positive, as an outcome with a weight of zero could never be selected.
+fn split_weight(input: &str) -> Result<(&str, u32), String> {
+    match input.split_once(':') {
+        Some((value, weight)) => {
+            let weight: u32 = weight.parse().map_err(|e| format!("Invalid weight: {e}"))?;
+            if weight == 0 {
+                return Err(String::from("Weight must be positive"));
+            }
+            Ok((value, weight))
+        }
+        None => Ok((input, 1)),
+    }
+}
+
 fn parse_seconds(input: &str) -> Result<f64, String> {
     let value: f64 = input.parse().map_err(|e| format!("{e}"))?;
-    if value < 0.0 {
+    if value.is_nan() || value < 0.0 {
         Err(String::from("Value must be a non-negative number"))
     } else {
         Ok(value)
     }
 }
 
-#[cfg(any(feature = "tcp-echo", feature = "udp-echo"))]
+fn parse_drain_period(input: &str) -> Result<f64, String> {
+    let period = parse_seconds(input)?;
+    if period.is_infinite() {
+        return Err(String::from("The drain period must be finite"));
+    }
+    Ok(period)
+}
+
+fn parse_probability(input: &str) -> Result<f64, String> {
+    let value: f64 = input.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=1.0).contains(&value) {
+        Err(String::from(
+            "Probability must be in range 0 to 1 (inclusive)",
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+fn parse_rate(input: &str) -> Result<f64, String> {
+    let value: f64 = input.parse().map_err(|e| format!("{e}"))?;
+    if !(value > 0.0 && value.is_finite()) {
+        Err(String::from("Value must be a positive number"))
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "http-echo")]
+fn parse_close_code(input: &str) -> Result<u16, String> {
+    let code: u16 = input.parse().map_err(|e| format!("{e}"))?;
+    if !is_valid_close_code(code) {
+        return Err(String::from(
+            "Close code must be in range 1000 to 1003, 1007 to 1014 or 3000 to 4999",
+        ));
+    }
+    Ok(code)
+}
+
+#[cfg(feature = "http-echo")]
+fn parse_liveness_fail_after(input: &str) -> Result<LivenessFailAfter, String> {
+    use std::time::Duration;
+    match input.strip_suffix('%') {
+        Some(percent) => {
+            let percent: f64 = percent.trim().parse().map_err(|e| format!("{e}"))?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(String::from(
+                    "Percentage must be in range 0 to 100 (inclusive)",
+                ));
+            }
+            Ok(LivenessFailAfter::Fraction(percent / 100.0))
+        }
+        None => Ok(LivenessFailAfter::Elapsed(
+            Duration::try_from_secs_f64(parse_seconds(input)?).unwrap_or(Duration::MAX),
+        )),
+    }
+}
+
+#[cfg(all(unix, feature = "unix-echo"))]
+fn parse_file_mode(input: &str) -> Result<u32, String> {
+    match u32::from_str_radix(input.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(String::from("Value must be an octal file mode, e.g. 660")),
+    }
+}
+
+#[cfg(any(feature = "tcp-echo", feature = "udp-echo", feature = "http-echo"))]
 fn parse_socket_addr(input: &str) -> Result<Vec<SocketAddr>, String> {
     use std::net::ToSocketAddrs;
     Ok(input
@@ -451,3 +1213,39 @@ fn parse_socket_addr(input: &str) -> Result<Vec<SocketAddr>, String> {
         .map_err(|e| format!("{e}"))?
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sequence_steps() {
+        assert_eq!(parse_sequence_step("1"), Ok((1, None)));
+        assert_eq!(parse_sequence_step("2@5.5"), Ok((2, Some(5.5))));
+        assert!(parse_sequence_step("1@inf").is_err());
+        assert!(parse_sequence_step("1@-1").is_err());
+        assert!(parse_sequence_step("256").is_err());
+    }
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(parse_weighted_exit_code("1"), Ok((1, 1)));
+        assert_eq!(parse_weighted_exit_code("1:80"), Ok((1, 80)));
+        assert_eq!(parse_weighted_signal("9:5"), Ok((9, 5)));
+        assert_eq!(
+            parse_weighted_crash_kind("segfault:3"),
+            Ok((CrashKind::Segfault, 3))
+        );
+        assert!(parse_weighted_exit_code("1:0").is_err());
+        assert!(parse_weighted_signal("9:0").is_err());
+        assert!(parse_weighted_crash_kind("panic:0").is_err());
+        assert!(parse_weighted_crash_kind("explode").is_err());
+    }
+
+    #[test]
+    fn rejects_infinite_drain_period() {
+        assert_eq!(parse_drain_period("2.5"), Ok(2.5));
+        assert!(parse_drain_period("inf").is_err());
+        assert!(Opts::try_parse_from(["crashie", "--drain-period", "inf"]).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use crate::crash::{crash, CrashKind, CrashOptions};
+use crate::termination::{terminate, TerminationMode};
+use std::fmt::{Display, Formatter};
+
+/// The way crashie ends once the delay has passed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// Exit with the specified code.
+    Exit(u8),
+    /// Crash with a genuine in-process failure.
+    Crash(CrashKind),
+}
+
+impl Outcome {
+    /// Ends the process according to this outcome.
+    ///
+    /// # Arguments
+    ///
+    /// * `termination_mode` - How exit codes are turned into process termination.
+    /// * `crash_options` - Parameters of the crash kinds.
+    pub fn execute(self, termination_mode: TerminationMode, crash_options: &CrashOptions) -> ! {
+        match self {
+            Outcome::Exit(exit_code) => terminate(exit_code, termination_mode),
+            Outcome::Crash(kind) => crash(kind, crash_options),
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Exit(exit_code) => write!(f, "exiting with code {exit_code}"),
+            Outcome::Crash(kind) => write!(f, "crashing with {kind}"),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+
+/// Determines how crashie reacts to SIGTERM and SIGINT.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ShutdownMode {
+    /// Install no signal handlers; the signal terminates the process immediately.
+    #[default]
+    Immediate,
+    /// Close the echo listeners, finish open connections for the drain period, then exit.
+    Drain,
+    /// Ignore the signal, forcing the supervisor to escalate to SIGKILL.
+    Ignore,
+}
+
+/// Installs handlers for SIGTERM and SIGINT according to the shutdown mode.
+///
+/// The signals are handled on a dedicated thread, such that draining works regardless of
+/// whether crashie is still waiting for its delay or already hanging.
+///
+/// # Arguments
+///
+/// * `mode` - The shutdown mode.
+/// * `drain_period` - The time to drain open connections for, in seconds.
+/// * `exit_code` - The exit code to use after draining.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if the signal handlers could not be registered.
+#[cfg(unix)]
+pub fn handle_shutdown_signals(
+    mode: ShutdownMode,
+    drain_period: f64,
+    exit_code: u8,
+) -> Result<(), std::io::Error> {
+    use crate::lifecycle::start_draining;
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+    use std::process::exit;
+    use std::thread::{self, sleep};
+    use std::time::Duration;
+
+    if mode == ShutdownMode::Immediate {
+        return Ok(());
+    }
+
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            match mode {
+                ShutdownMode::Immediate => unreachable!("no handler is installed"),
+                ShutdownMode::Ignore => println!("Ignoring signal {signal}"),
+                ShutdownMode::Drain => {
+                    println!("Received signal {signal}, draining for {drain_period:.2} seconds");
+                    start_draining();
+                    sleep(Duration::from_secs_f64(drain_period));
+
+                    println!("Exiting with code {exit_code}");
+                    exit(exit_code as i32)
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Signal handling is not supported on this platform.
+#[cfg(not(unix))]
+pub fn handle_shutdown_signals(
+    mode: ShutdownMode,
+    _drain_period: f64,
+    _exit_code: u8,
+) -> Result<(), std::io::Error> {
+    if mode != ShutdownMode::Immediate {
+        eprintln!("Handling shutdown signals is not supported on this platform");
+    }
+    Ok(())
+}
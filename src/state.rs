@@ -0,0 +1,54 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// State that persists across restarts of crashie.
+#[derive(Debug)]
+pub struct RunState {
+    /// The file the state is stored in.
+    path: PathBuf,
+    /// The number of runs before the current one.
+    pub run: u64,
+}
+
+impl RunState {
+    /// Loads the state from the given file and records the current run.
+    ///
+    /// The run counter is persisted immediately, since the current run may end
+    /// in a crash that leaves no opportunity to do so later.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file. If the file does not exist, it is created.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an `std::io::Error` if the state file cannot be read, parsed or written.
+    pub fn begin_run(path: &Path) -> Result<Self, std::io::Error> {
+        let run = match fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse().map_err(|e| {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid run counter in state file: {e}"),
+                )
+            })?,
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+
+        let state = Self {
+            path: path.to_path_buf(),
+            run,
+        };
+        state.store(run + 1)?;
+        Ok(state)
+    }
+
+    /// Atomically writes the run counter to the state file.
+    fn store(&self, runs: u64) -> Result<(), std::io::Error> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, format!("{runs}\n"))?;
+        fs::rename(&temp, &self.path)
+    }
+}
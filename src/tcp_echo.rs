@@ -1,22 +1,62 @@
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::thread;
+use crate::delay::Delay;
+use crate::lifecycle::{park_forever, stall_if_frozen};
+use crate::listener::accept_connections;
+use rand::rngs::StdRng;
+#[cfg(feature = "tls")]
+use rustls::ServerConfig;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// The configuration of the TCP, TLS and Unix domain stream echo servers.
+pub struct TcpEchoConfig {
+    /// The delay before echoing each chunk of received data.
+    pub latency: Option<Delay>,
+    /// The maximum rate at which data is echoed back per connection, in bytes per second.
+    pub bandwidth: Option<f64>,
+    /// The maximum number of bytes read and echoed back at once.
+    pub buffer_size: usize,
+    /// The random number generator for sampling delays.
+    pub rng: Mutex<StdRng>,
+}
+
+impl TcpEchoConfig {
+    /// Sleeps for a duration sampled from the latency, if any.
+    fn delay(&self) {
+        if let Some(latency) = &self.latency {
+            let duration =
+                latency.sample_duration(&mut *self.rng.lock().expect("RNG lock poisoned"));
+            sleep(duration);
+        }
+    }
+}
 
 /// Listens for TCP connections on the given address and spawns a new thread for each
-/// accepted connection.
+/// accepted connection. The listener is closed when the process starts draining.
 ///
 /// # Arguments
 ///
 /// * `addr` - The address to bind the TCP listener to.
+/// * `config` - The latency, bandwidth and buffer size of the connections.
 ///
 /// # Examples
 ///
 /// ```no_run
+/// use rand::{rngs::StdRng, SeedableRng};
 /// use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+/// use std::sync::{Arc, Mutex};
 ///
 /// let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+/// let config = Arc::new(TcpEchoConfig {
+///     latency: None,
+///     bandwidth: None,
+///     buffer_size: 512,
+///     rng: Mutex::new(StdRng::seed_from_u64(0)),
+/// });
 ///
-/// if let Err(e) = tcp_echo(&addr) {
+/// if let Err(e) = tcp_echo(&addr, config) {
 ///     eprintln!("Error occurred while running TCP echo server: {:?}", e);
 /// }
 /// ```
@@ -25,39 +65,58 @@ use std::thread;
 ///
 /// This function returns an `std::io::Error` if there is an error binding the TCP listener to the
 /// given address, or if there is an error accepting a connection.
-pub fn tcp_echo(addr: &SocketAddr) -> Result<(), std::io::Error> {
+pub fn tcp_echo(addr: &SocketAddr, config: Arc<TcpEchoConfig>) -> Result<(), std::io::Error> {
     let listener = TcpListener::bind(addr)?;
     println!("Listening for TCP connections on {addr}");
+    accept_connections(listener, "TCP", move |stream| {
+        handle_client(stream, &config)
+    })
+}
 
-    thread::spawn(move || {
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    println!(
-                        "Accepting TCP connection from {}",
-                        stream.peer_addr().expect("Unable to obtain peer address")
-                    );
-                    thread::spawn(move || handle_client(stream));
-                }
-                Err(e) => {
-                    eprintln!("Error accepting TCP connection: {e}");
-                }
-            }
+/// Listens for TLS connections on the given address and echoes the decrypted data back.
+///
+/// # Arguments
+///
+/// * `addr` - The address to bind the TCP listener to.
+/// * `config` - The latency, bandwidth and buffer size of the connections.
+/// * `tls_config` - The TLS server configuration.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if there is an error binding the TCP listener to the
+/// given address.
+#[cfg(feature = "tls")]
+pub fn tls_echo(
+    addr: &SocketAddr,
+    config: Arc<TcpEchoConfig>,
+    tls_config: Arc<ServerConfig>,
+) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening for TLS connections on {addr}");
+    accept_connections(listener, "TLS", move |stream| {
+        match crate::tls::accept(&tls_config, stream) {
+            Ok(stream) => handle_client(stream, &config),
+            Err(e) => eprintln!("TLS handshake failed: {e}"),
         }
-    });
-    Ok(())
+    })
 }
 
 /// Handles the client connection and echoes the received data back.
 ///
-/// This function reads data from the provided `TcpStream` and writes it back to the stream.
+/// This function reads data from the provided stream and writes it back to the stream.
 /// It operates in a loop until the client closes the connection or an error occurs.
+/// Each chunk of data is delayed by the configured latency, and the chunks are paced
+/// such that the echoed data does not exceed the configured bandwidth.
 ///
 /// # Arguments
 ///
-/// * `stream` - A `TcpStream` representing the client connection.
-fn handle_client(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
+/// * `stream` - A TCP, TLS or Unix domain stream representing the client connection.
+/// * `config` - The latency, bandwidth and buffer size of the connection.
+pub fn handle_client<S: Read + Write>(mut stream: S, config: &TcpEchoConfig) {
+    let mut buffer = vec![0; config.buffer_size];
+
+    // The earliest time at which the next chunk may be echoed.
+    let mut next_write = Instant::now();
 
     loop {
         match stream.read(&mut buffer) {
@@ -66,12 +125,33 @@ fn handle_client(mut stream: TcpStream) {
                 return;
             }
             Ok(n) => {
+                stall_if_frozen();
+                config.delay();
+
+                if let Some(bandwidth) = config.bandwidth {
+                    let now = Instant::now();
+                    if next_write > now {
+                        sleep(next_write - now);
+                    }
+                    let transfer_time =
+                        Duration::try_from_secs_f64(n as f64 / bandwidth).unwrap_or(Duration::MAX);
+                    // At rates this low, the data would never be echoed.
+                    next_write = match next_write.max(now).checked_add(transfer_time) {
+                        Some(next_write) => next_write,
+                        None => park_forever(),
+                    };
+                }
+
                 // Echo everything back.
                 if let Err(e) = stream.write_all(&buffer[0..n]) {
                     eprintln!("Failed to write to socket: {e}");
                     return;
                 }
             }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                // The client has closed a TLS connection without notice.
+                return;
+            }
             Err(e) => {
                 eprintln!("Failed to read from socket: {e}");
                 return;
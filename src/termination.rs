@@ -0,0 +1,69 @@
+use clap::ValueEnum;
+use std::process::exit;
+
+/// Determines how crashie terminates once the exit code was selected.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TerminationMode {
+    /// Exit normally; signals are reported as exit code 128+SIGNAL.
+    #[default]
+    Exit,
+    /// Raise the signal for exit codes 129 to 159, so that the process is terminated by it.
+    Raise,
+}
+
+/// Terminates the process with the specified exit code.
+///
+/// In [`TerminationMode::Raise`], exit codes in the range 129 to 159 are interpreted
+/// as 128+SIGNAL and the signal is raised after resetting its handler to the default action.
+/// If the signal does not terminate the process (e.g. because its default action is to ignore
+/// it), the process exits with the exit code instead.
+///
+/// # Arguments
+///
+/// * `exit_code` - The exit code to terminate with.
+/// * `mode` - The termination mode.
+pub fn terminate(exit_code: u8, mode: TerminationMode) -> ! {
+    if mode == TerminationMode::Raise {
+        if let Some(signal) = exit_to_signal(exit_code) {
+            raise(signal);
+        }
+    }
+
+    println!("Exiting with code {exit_code}");
+    exit(exit_code as i32)
+}
+
+/// Raises the specified signal with its default disposition.
+#[cfg(unix)]
+fn raise(signal: u8) {
+    println!("Raising signal {signal}");
+    let signal = signal as libc::c_int;
+
+    // SAFETY: All calls operate on a valid signal number and a stack-allocated signal set.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+
+        libc::raise(signal);
+    }
+
+    eprintln!("Signal {signal} did not terminate the process");
+}
+
+/// Raising signals is not supported on this platform.
+#[cfg(not(unix))]
+fn raise(signal: u8) {
+    eprintln!("Raising signal {signal} is not supported on this platform");
+}
+
+/// Converts an exit code in the range 129 to 159 back to its signal number.
+const fn exit_to_signal(exit_code: u8) -> Option<u8> {
+    match exit_code {
+        129..=159 => Some(exit_code - 128),
+        _ => None,
+    }
+}
@@ -0,0 +1,283 @@
+use rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::fs;
+use std::io::ErrorKind;
+#[cfg(feature = "http-echo")]
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The time a client has to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TLS stream over an accepted TCP connection.
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Provides the server certificate for TLS handshakes.
+///
+/// Certificates loaded from files are reloaded when either file changes, such that
+/// rotated certificates are picked up by new connections without a restart.
+#[derive(Debug)]
+pub struct CertificateResolver {
+    /// The certificate chain and private key files, if not self-signed.
+    files: Option<(PathBuf, PathBuf)>,
+    /// The modification times of the files and the certificate loaded from them.
+    current: Mutex<(Option<SystemTime>, Option<SystemTime>, Arc<CertifiedKey>)>,
+}
+
+impl CertificateResolver {
+    /// Loads the certificate chain and private key from PEM files.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a description of the problem if the files cannot be read
+    /// or do not contain a usable certificate and key.
+    pub fn from_files(cert_path: &Path, key_path: &Path) -> Result<Self, String> {
+        let modified = (modified(cert_path), modified(key_path));
+        let key = load_certified_key(cert_path, key_path)?;
+        Ok(Self {
+            files: Some((cert_path.to_path_buf(), key_path.to_path_buf())),
+            current: Mutex::new((modified.0, modified.1, key)),
+        })
+    }
+
+    /// Generates a self-signed certificate for the given host names.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a description of the problem if the certificate cannot be generated.
+    pub fn self_signed(names: &[String]) -> Result<Self, String> {
+        let generated = rcgen::generate_simple_self_signed(names.to_vec())
+            .map_err(|e| format!("Failed to generate self-signed certificate: {e}"))?;
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der()));
+        let key = certified_key(vec![generated.cert.der().clone()], &key)?;
+        Ok(Self {
+            files: None,
+            current: Mutex::new((None, None, key)),
+        })
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let mut current = self.current.lock().expect("Certificate lock poisoned");
+        if let Some((cert_path, key_path)) = &self.files {
+            let modified = (modified(cert_path), modified(key_path));
+            if modified != (current.0, current.1) {
+                match load_certified_key(cert_path, key_path) {
+                    Ok(key) => {
+                        println!("Reloaded TLS certificate from {}", cert_path.display());
+                        *current = (modified.0, modified.1, key);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to reload TLS certificate, keeping the previous one: {e}")
+                    }
+                }
+            }
+        }
+        Some(current.2.clone())
+    }
+}
+
+/// Creates the server configuration for a TLS listener.
+///
+/// # Arguments
+///
+/// * `certificates` - The resolver providing the server certificate.
+/// * `alpn_protocols` - The application protocols to offer, in order of preference.
+///
+/// # Errors
+///
+/// This function returns a description of the problem if the configuration is invalid.
+pub fn server_config(
+    certificates: Arc<CertificateResolver>,
+    alpn_protocols: &[&[u8]],
+) -> Result<Arc<ServerConfig>, String> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates);
+    config.alpn_protocols = alpn_protocols.iter().map(|p| p.to_vec()).collect();
+    Ok(Arc::new(config))
+}
+
+/// Performs the TLS handshake on an accepted connection.
+///
+/// Clients that do not complete the handshake within [`HANDSHAKE_TIMEOUT`] are disconnected,
+/// such that they cannot hold on to a connection thread without ever sending a request.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if the handshake fails or times out.
+pub fn accept(config: &Arc<ServerConfig>, mut stream: TcpStream) -> std::io::Result<TlsStream> {
+    let mut connection = ServerConnection::new(config.clone()).map_err(std::io::Error::other)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    while connection.is_handshaking() {
+        match connection.complete_io(&mut stream) {
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "Timed out waiting for the client",
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(StreamOwned::new(connection, stream))
+}
+
+/// The reading half of a split TLS stream.
+#[cfg(feature = "http-echo")]
+pub struct TlsReader {
+    connection: Arc<Mutex<ServerConnection>>,
+    socket: TcpStream,
+    /// Received TLS records not yet processed.
+    pending: Vec<u8>,
+}
+
+/// The writing half of a split TLS stream.
+#[cfg(feature = "http-echo")]
+pub struct TlsWriter {
+    connection: Arc<Mutex<ServerConnection>>,
+    socket: TcpStream,
+}
+
+/// Splits a TLS stream such that it can be read and written from different threads.
+///
+/// The connection state is shared between both halves, while reading from the socket
+/// happens without holding the lock.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if the socket cannot be cloned.
+#[cfg(feature = "http-echo")]
+pub fn split(stream: TlsStream) -> std::io::Result<(TlsReader, TlsWriter)> {
+    let StreamOwned { conn, sock } = stream;
+    let connection = Arc::new(Mutex::new(conn));
+    let reader = TlsReader {
+        connection: connection.clone(),
+        socket: sock.try_clone()?,
+        pending: Vec::new(),
+    };
+    Ok((
+        reader,
+        TlsWriter {
+            connection,
+            socket: sock,
+        },
+    ))
+}
+
+#[cfg(feature = "http-echo")]
+impl Read for TlsReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let mut connection = self.connection.lock().expect("TLS lock poisoned");
+            match connection.reader().read(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            if self.pending.is_empty() {
+                drop(connection);
+                let mut incoming = [0; 16 * 1024];
+                let length = self.socket.read(&mut incoming)?;
+                self.pending.extend_from_slice(&incoming[..length]);
+                connection = self.connection.lock().expect("TLS lock poisoned");
+
+                // Signal the end of the stream to the connection.
+                if length == 0 {
+                    connection.read_tls(&mut &[][..])?;
+                    continue;
+                }
+            }
+
+            let consumed = connection.read_tls(&mut self.pending.as_slice())?;
+            self.pending.drain(..consumed);
+            let result = connection.process_new_packets();
+            while connection.wants_write() {
+                connection.write_tls(&mut self.socket)?;
+            }
+            result.map_err(std::io::Error::other)?;
+        }
+    }
+}
+
+#[cfg(feature = "http-echo")]
+impl Write for TlsWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut connection = self.connection.lock().expect("TLS lock poisoned");
+        let length = connection.writer().write(buf)?;
+        while connection.wants_write() {
+            connection.write_tls(&mut self.socket)?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut connection = self.connection.lock().expect("TLS lock poisoned");
+        connection.writer().flush()?;
+        while connection.wants_write() {
+            connection.write_tls(&mut self.socket)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads a certificate chain and private key from PEM files.
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<Arc<CertifiedKey>, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| {
+            format!(
+                "Failed to read certificates from {}: {e}",
+                cert_path.display()
+            )
+        })?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| {
+        format!(
+            "Failed to read private key from {}: {e}",
+            key_path.display()
+        )
+    })?;
+    certified_key(certs, &key)
+}
+
+/// Combines a certificate chain with its private key.
+///
+/// A key not matching the certificate is rejected, such as while only one of the files
+/// has been replaced during rotation.
+fn certified_key(
+    certs: Vec<CertificateDer<'static>>,
+    key: &PrivateKeyDer<'_>,
+) -> Result<Arc<CertifiedKey>, String> {
+    let signing_key =
+        any_supported_type(key).map_err(|e| format!("Unsupported private key: {e}"))?;
+    let certified_key = CertifiedKey::new(certs, signing_key);
+    certified_key
+        .keys_match()
+        .map_err(|e| format!("Private key does not match the certificate: {e}"))?;
+    Ok(Arc::new(certified_key))
+}
+
+/// Returns the modification time of a file, if available.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
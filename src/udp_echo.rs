@@ -1,7 +1,10 @@
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use std::io::ErrorKind;
 use std::net::{SocketAddr, UdpSocket};
 use std::thread;
 
 /// Listens for UDP datagrams on the given address and echoes each received datagram back to the sender.
+/// The socket is closed when the process starts draining.
 ///
 /// # Arguments
 ///
@@ -25,23 +28,29 @@ use std::thread;
 /// given address.
 pub fn udp_echo(addr: &SocketAddr) -> Result<(), std::io::Error> {
     let socket = UdpSocket::bind(addr)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let addr = *addr;
     println!("Listening for UDP datagrams on {addr}");
 
     thread::spawn(move || {
         let mut buffer = [0; 512];
 
-        loop {
+        while !is_draining() {
             match socket.recv_from(&mut buffer) {
                 Ok((size, src)) => {
+                    stall_if_frozen();
                     if let Err(e) = socket.send_to(&buffer[0..size], src) {
                         eprintln!("Failed to echo UDP datagram: {}", e);
                     }
                 }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
                 Err(e) => {
                     eprintln!("Failed to receive UDP datagram: {}", e);
                 }
             }
         }
+
+        println!("Closing UDP socket on {addr}");
     });
     Ok(())
 }
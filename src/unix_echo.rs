@@ -0,0 +1,255 @@
+use crate::lifecycle::{is_draining, stall_if_frozen, POLL_INTERVAL};
+use crate::listener::{accept_connections, Listener};
+use crate::tcp_echo::{handle_client, TcpEchoConfig};
+use std::fmt::{Display, Formatter};
+use std::fs::{self, Permissions};
+use std::io::ErrorKind;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+/// The largest datagram echoed back in full.
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// The address of a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixSocketPath {
+    /// A socket file in the file system.
+    File(PathBuf),
+    /// A name in the abstract namespace, written with a leading `@`.
+    Abstract(String),
+}
+
+impl UnixSocketPath {
+    fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        match self {
+            UnixSocketPath::File(path) => SocketAddr::from_pathname(path),
+            #[cfg(target_os = "linux")]
+            UnixSocketPath::Abstract(name) => SocketAddr::from_abstract_name(name),
+            #[cfg(not(target_os = "linux"))]
+            UnixSocketPath::Abstract(_) => Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Abstract socket names are only supported on Linux",
+            )),
+        }
+    }
+}
+
+impl FromStr for UnixSocketPath {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.strip_prefix('@') {
+            Some("") => Err(String::from("The abstract socket name must not be empty")),
+            Some(_) if cfg!(not(target_os = "linux")) => Err(String::from(
+                "Abstract socket names are only supported on Linux",
+            )),
+            Some(name) => Ok(UnixSocketPath::Abstract(String::from(name))),
+            None if input.is_empty() => Err(String::from("The socket path must not be empty")),
+            None => Ok(UnixSocketPath::File(PathBuf::from(input))),
+        }
+    }
+}
+
+impl Display for UnixSocketPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnixSocketPath::File(path) => write!(f, "{}", path.display()),
+            UnixSocketPath::Abstract(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+/// A bound Unix domain socket, whose socket file is removed when it is closed.
+struct UnixSocket<S> {
+    socket: S,
+    path: UnixSocketPath,
+}
+
+impl<S> UnixSocket<S> {
+    /// Binds the socket, replacing a stale socket file left behind by a process that is gone.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The address to bind the socket to.
+    /// * `mode` - The permissions of the socket file, if any.
+    /// * `bind` - Binds a socket to the address.
+    /// * `connect` - Connects to a socket of the same type at the address.
+    fn bind<B, C>(
+        path: &UnixSocketPath,
+        mode: Option<u32>,
+        bind: B,
+        connect: C,
+    ) -> Result<Self, std::io::Error>
+    where
+        B: Fn(&SocketAddr) -> Result<S, std::io::Error>,
+        C: Fn(&SocketAddr) -> Result<(), std::io::Error>,
+    {
+        let addr = path.socket_addr()?;
+        // Create the socket file with the requested permissions, such that it is never accessible
+        // more widely before they are set.
+        let bind = |addr: &SocketAddr| with_umask(mode, || bind(addr));
+        let socket = match (bind(&addr), path) {
+            (Err(e), UnixSocketPath::File(file)) if e.kind() == ErrorKind::AddrInUse => {
+                // Only sockets nobody is listening on anymore are removed.
+                let is_socket = fs::metadata(file)?.file_type().is_socket();
+                if !is_socket
+                    || !connect(&addr).is_err_and(|e| e.kind() == ErrorKind::ConnectionRefused)
+                {
+                    return Err(e);
+                }
+                println!("Removing stale socket {}", file.display());
+                fs::remove_file(file)?;
+                bind(&addr)?
+            }
+            (socket, _) => socket?,
+        };
+
+        // Wrap the socket first, such that its file is removed if the permissions cannot be set.
+        // Setting them explicitly also applies bits a umask cannot grant, e.g. the sticky bit.
+        let socket = Self {
+            socket,
+            path: path.clone(),
+        };
+        if let (Some(mode), UnixSocketPath::File(file)) = (mode, path) {
+            fs::set_permissions(file, Permissions::from_mode(mode))?;
+        }
+        Ok(socket)
+    }
+}
+
+impl<S> Drop for UnixSocket<S> {
+    fn drop(&mut self) {
+        if let UnixSocketPath::File(file) = &self.path {
+            if let Err(e) = fs::remove_file(file) {
+                eprintln!("Failed to remove socket {}: {e}", file.display());
+            }
+        }
+    }
+}
+
+impl Listener for UnixSocket<UnixListener> {
+    type Stream = UnixStream;
+
+    fn describe(&self) -> Result<String, std::io::Error> {
+        Ok(self.path.to_string())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    fn accept_connection(&self) -> Result<(UnixStream, String), std::io::Error> {
+        let (stream, peer) = self.socket.accept()?;
+        // Accepted sockets may inherit the non-blocking mode on some platforms.
+        stream.set_nonblocking(false)?;
+        Ok((stream, describe_peer(&peer)))
+    }
+}
+
+/// Listens for connections on the given Unix domain socket and echoes the received data back.
+/// The listener is closed and its socket file removed when the process starts draining.
+///
+/// # Arguments
+///
+/// * `path` - The socket file or abstract name to bind the listener to.
+/// * `mode` - The permissions of the socket file, if any.
+/// * `config` - The latency, bandwidth and buffer size of the connections.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if there is an error binding the listener to the
+/// given path, e.g. because another process is listening on it.
+pub fn unix_echo(
+    path: &UnixSocketPath,
+    mode: Option<u32>,
+    config: Arc<TcpEchoConfig>,
+) -> Result<(), std::io::Error> {
+    let listener = UnixSocket::bind(path, mode, UnixListener::bind_addr, |addr| {
+        UnixStream::connect_addr(addr).map(drop)
+    })?;
+    println!("Listening for Unix connections on {path}");
+    accept_connections(listener, "Unix", move |stream| {
+        handle_client(stream, &config)
+    })
+}
+
+/// Listens for datagrams on the given Unix domain socket and echoes each received datagram back
+/// to the sender. The socket is closed and its socket file removed when the process starts
+/// draining.
+///
+/// Datagrams can only be echoed if the sender bound its socket to an address.
+///
+/// # Arguments
+///
+/// * `path` - The socket file or abstract name to bind the socket to.
+/// * `mode` - The permissions of the socket file, if any.
+///
+/// # Errors
+///
+/// This function returns an `std::io::Error` if there is an error binding the socket to the
+/// given path, e.g. because another process is bound to it.
+pub fn unixgram_echo(path: &UnixSocketPath, mode: Option<u32>) -> Result<(), std::io::Error> {
+    let socket = UnixSocket::bind(path, mode, UnixDatagram::bind_addr, |addr| {
+        UnixDatagram::unbound()?.connect_addr(addr)
+    })?;
+    socket.socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    println!("Listening for Unix datagrams on {path}");
+
+    thread::spawn(move || {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+
+        while !is_draining() {
+            match socket.socket.recv_from(&mut buffer) {
+                Ok((_, src)) if src.is_unnamed() => {
+                    eprintln!("Cannot echo Unix datagram from an unnamed socket");
+                }
+                Ok((size, src)) => {
+                    stall_if_frozen();
+                    if let Err(e) = socket.socket.send_to_addr(&buffer[0..size], &src) {
+                        eprintln!("Failed to echo Unix datagram: {e}");
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    eprintln!("Failed to receive Unix datagram: {e}");
+                }
+            }
+        }
+
+        println!("Closing Unix datagram socket on {}", socket.path);
+    });
+    Ok(())
+}
+
+/// Runs the function with a umask that restricts newly created files to the given permissions.
+///
+/// The umask is process-wide, so this is only used while the listeners are being set up.
+fn with_umask<T>(mode: Option<u32>, f: impl FnOnce() -> T) -> T {
+    let Some(mode) = mode else {
+        return f();
+    };
+    // SAFETY: umask cannot fail and only replaces the file mode creation mask.
+    let previous = unsafe { libc::umask((!mode & 0o777) as libc::mode_t) };
+    let result = f();
+    // SAFETY: As above, restoring the previous mask.
+    unsafe { libc::umask(previous) };
+    result
+}
+
+/// Describes the address of a peer, which is usually unnamed for connecting sockets.
+fn describe_peer(addr: &SocketAddr) -> String {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = addr.as_abstract_name() {
+        return format!("@{}", String::from_utf8_lossy(name));
+    }
+    match addr.as_pathname() {
+        Some(path) => path.display().to_string(),
+        None => String::from("an unnamed socket"),
+    }
+}